@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::{AuthzLayer, DecodingKey, EncodingKey, KeysConfig, Policy};
+
+/// The `auth` section of the configs of the services and the proxy. No key is
+/// shipped, generate one with `token keygen` of crm and configure its public key.
+///
+/// ```yaml
+/// auth:
+///   # the public key of the tokens without key id
+///   pk: |
+///     -----BEGIN PUBLIC KEY-----
+///     ...
+///     -----END PUBLIC KEY-----
+///   # the keys with an id, see `KeysConfig`
+///   keys:
+///     dir: /etc/crm/keys
+///   policy:
+///     roles:
+///       admin: [campaign:run]
+///     methods:
+///       /crm.Crm: [campaign:run]
+///   # the key signing the tokens of the calls of the service itself
+///   sk: /etc/crm/sk.pem
+///   kid: crm
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthConfig {
+    /// public key of the tokens without key id
+    #[serde(default)]
+    pub pk: Option<String>,
+    /// public keys of the tokens with a key id, reloaded when changed
+    #[serde(default)]
+    pub keys: KeysConfig,
+    /// scopes required by the methods of the service
+    #[serde(default)]
+    pub policy: Policy,
+    /// pem file of the key signing the tokens of the calls of the service
+    #[serde(default)]
+    pub sk: Option<PathBuf>,
+    /// key id of the signed tokens
+    #[serde(default)]
+    pub kid: Option<String>,
+}
+
+impl AuthConfig {
+    /// The keys verifying the tokens, reloaded in the background whenever their
+    /// files change. Must be called within a tokio runtime.
+    pub fn decoding_key(&self) -> Result<DecodingKey, jwt_simple::Error> {
+        let dk = DecodingKey::from_config(self.pk.as_deref(), &self.keys)?;
+        dk.spawn_reload();
+        Ok(dk)
+    }
+
+    /// The key of `sk` if configured
+    pub fn encoding_key(&self) -> Result<Option<EncodingKey>, jwt_simple::Error> {
+        let Some(sk) = &self.sk else {
+            return Ok(None);
+        };
+        let key = EncodingKey::from_file(sk)?;
+        Ok(Some(match &self.kid {
+            Some(kid) => key.with_key_id(kid),
+            None => key,
+        }))
+    }
+
+    pub fn authz_layer(&self) -> AuthzLayer {
+        AuthzLayer::new(self.policy.clone())
+    }
+}
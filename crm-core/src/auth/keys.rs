@@ -0,0 +1,165 @@
+use std::{collections::HashSet, fs, io::ErrorKind, path::PathBuf, time::SystemTime};
+
+use anyhow::{anyhow, bail, Context, Result};
+use jwt_simple::prelude::*;
+
+const KTY: &str = "OKP";
+const CRV: &str = "Ed25519";
+
+/// Where the public keys verifying the tokens are loaded from, in addition to
+/// the inline `pk` of the auth config.
+///
+/// ```yaml
+/// keys:
+///   # every `<kid>.pem` file of the directory
+///   dir: /etc/crm/keys
+///   jwks: /etc/crm/jwks.json
+///   deny_list: /etc/crm/revoked
+///   reload_interval: 30
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeysConfig {
+    /// directory of PEM public keys, the key id of a key is its file name without extension
+    pub dir: Option<PathBuf>,
+    /// a JWKS document of Ed25519 keys
+    pub jwks: Option<PathBuf>,
+    /// the ids or subjects of the revoked tokens, one per line
+    pub deny_list: Option<PathBuf>,
+    /// interval in seconds between checks of the files for changes
+    #[serde(default = "default_reload_interval")]
+    pub reload_interval: u64,
+}
+
+/// A JWKS document
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// An Ed25519 public key in the JWK format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    pub kid: Option<String>,
+    /// the base64url encoded public key
+    pub x: String,
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            jwks: None,
+            deny_list: None,
+            reload_interval: default_reload_interval(),
+        }
+    }
+}
+
+impl KeysConfig {
+    /// Load the public keys of the files
+    pub fn load(&self) -> Result<Vec<Ed25519PublicKey>> {
+        let mut keys = vec![];
+        for path in self.pem_files()? {
+            let kid = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .ok_or_else(|| anyhow!("invalid key file name: {}", path.display()))?;
+            let pem = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            let key = Ed25519PublicKey::from_pem(&pem)
+                .with_context(|| format!("invalid public key {}", path.display()))?;
+            keys.push(key.with_key_id(kid));
+        }
+
+        if let Some(path) = &self.jwks {
+            let jwks = fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            let jwks: Jwks = serde_json::from_str(&jwks)
+                .with_context(|| format!("invalid JWKS {}", path.display()))?;
+            for jwk in jwks.keys {
+                keys.push(jwk.to_public_key()?);
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Load the deny-list, a missing file is an empty list
+    pub fn load_deny_list(&self) -> Result<HashSet<String>> {
+        let Some(path) = &self.deny_list else {
+            return Ok(HashSet::new());
+        };
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(content
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(str::to_string)
+                .collect()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(HashSet::new()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// The files and their modification time, changed when a file is added,
+    /// removed or modified
+    pub(super) fn fingerprint(&self) -> Vec<(PathBuf, Option<SystemTime>)> {
+        let mut files = self.pem_files().unwrap_or_default();
+        files.extend(self.jwks.clone());
+        files.extend(self.deny_list.clone());
+        files
+            .into_iter()
+            .map(|path| {
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+                (path, modified)
+            })
+            .collect()
+    }
+
+    fn pem_files(&self) -> Result<Vec<PathBuf>> {
+        let Some(dir) = &self.dir else {
+            return Ok(vec![]);
+        };
+        let mut files = vec![];
+        for entry in
+            fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "pem") {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl Jwk {
+    pub fn from_public_key(key: &Ed25519PublicKey) -> Self {
+        Self {
+            kty: KTY.to_string(),
+            crv: CRV.to_string(),
+            kid: key.key_id().clone(),
+            x: Base64UrlSafeNoPadding::encode_to_string(key.to_bytes())
+                .expect("public key is encodable"),
+        }
+    }
+
+    pub fn to_public_key(&self) -> Result<Ed25519PublicKey> {
+        if self.kty != KTY || self.crv != CRV {
+            bail!("unsupported key type: {} {}", self.kty, self.crv);
+        }
+        let raw = Base64UrlSafeNoPadding::decode_to_vec(&self.x, None)
+            .map_err(|e| anyhow!("invalid key {:?}: {e}", self.kid))?;
+        let key = Ed25519PublicKey::from_bytes(&raw)?;
+        Ok(match &self.kid {
+            Some(kid) => key.with_key_id(kid),
+            None => key,
+        })
+    }
+}
+
+fn default_reload_interval() -> u64 {
+    30
+}
@@ -0,0 +1,138 @@
+use std::task::{Context, Poll};
+
+use futures::future::{self, Either, Ready};
+use tower::{Layer, Service};
+
+use super::{status_response, DecodingKey, AUTHORIZATION};
+
+/// Paths served without authentication, so that the probes and `grpcurl list`
+/// need no token
+const PUBLIC_PATHS: &[&str] = &[
+    "/grpc.health.v1.Health/",
+    "/grpc.reflection.v1.ServerReflection/",
+    "/grpc.reflection.v1alpha.ServerReflection/",
+];
+
+/// A layer authenticating the calls with their bearer token like the `DecodingKey`
+/// interceptor, except for the health checks and the reflection
+#[derive(Debug, Clone)]
+pub struct AuthLayer {
+    key: DecodingKey,
+}
+
+#[derive(Debug, Clone)]
+pub struct Auth<S> {
+    inner: S,
+    key: DecodingKey,
+}
+
+impl AuthLayer {
+    pub fn new(key: DecodingKey) -> Self {
+        Self { key }
+    }
+}
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = Auth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Auth {
+            inner,
+            key: self.key.clone(),
+        }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for Auth<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<Ready<Result<Self::Response, Self::Error>>, S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<ReqBody>) -> Self::Future {
+        let path = req.uri().path();
+        if PUBLIC_PATHS.iter().any(|p| path.starts_with(p)) {
+            return Either::Right(self.inner.call(req));
+        }
+
+        let authorization = req
+            .headers()
+            .get(AUTHORIZATION)
+            .map(|v| v.to_str().unwrap_or_default());
+        match self.key.authenticate(authorization) {
+            Ok(user) => {
+                req.extensions_mut().insert(user);
+                Either::Right(self.inner.call(req))
+            }
+            Err(e) => Either::Left(future::ok(status_response(e.into()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{EncodingKey, User};
+    use tonic::Status;
+
+    #[tokio::test]
+    async fn calls_should_be_authenticated_except_health_checks_and_reflection() {
+        let svc = tower::service_fn(|req: http::Request<()>| async move {
+            let name = req
+                .extensions()
+                .get::<User>()
+                .map(|u| u.name.clone())
+                .unwrap_or_default();
+            Ok::<_, Status>(http::Response::new(name))
+        });
+        let ek = EncodingKey::generate();
+        let dk = DecodingKey::load(&ek.public_key().to_pem()).unwrap();
+        let mut svc = AuthLayer::new(dk).layer(svc);
+
+        let req = http::Request::builder()
+            .uri("/grpc.health.v1.Health/Check")
+            .body(())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert!(res.headers().get("grpc-status").is_none());
+
+        for path in [
+            "/grpc.reflection.v1.ServerReflection/ServerReflectionInfo",
+            "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo",
+        ] {
+            let req = http::Request::builder().uri(path).body(()).unwrap();
+            let res = svc.call(req).await.unwrap();
+            assert!(res.headers().get("grpc-status").is_none(), "{path}");
+        }
+
+        let req = http::Request::builder()
+            .uri("/crm.Crm/Welcome")
+            .body(())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.headers()["grpc-status"], "16");
+
+        let token = ek
+            .sign(User {
+                name: "John Doe".to_string(),
+                email: "john.doe@example.com".to_string(),
+                roles: vec![],
+                scopes: vec![],
+            })
+            .unwrap();
+        let req = http::Request::builder()
+            .uri("/crm.Crm/Welcome")
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.body(), "John Doe");
+    }
+}
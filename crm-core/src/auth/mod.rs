@@ -0,0 +1,524 @@
+mod config;
+mod keys;
+mod layer;
+mod policy;
+
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::Duration as StdDuration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use http::{header::CONTENT_TYPE, HeaderValue};
+use jwt_simple::{
+    prelude::*,
+    reexports::{
+        ct_codecs::Hex,
+        rand::{self, RngCore},
+    },
+};
+use tokio::task::JoinHandle;
+use tonic::{
+    metadata::{errors::InvalidMetadataValue, MetadataValue},
+    service::Interceptor,
+    Request, Status,
+};
+use tracing::{debug, info, warn};
+
+pub use config::AuthConfig;
+pub use keys::{Jwk, Jwks, KeysConfig};
+pub use layer::{Auth, AuthLayer};
+pub use policy::{Authz, AuthzLayer, Denied, Policy};
+
+const AUTHORIZATION: &str = "authorization";
+const BEARER: &str = "Bearer ";
+
+/// The public keys verifying the tokens, the tokens with a key id are verified by
+/// the key of that id only. The keys are shared by the clones, so that a reload is
+/// seen by every interceptor.
+#[derive(Debug, Clone)]
+pub struct DecodingKey {
+    keys: Arc<RwLock<KeySet>>,
+    source: Arc<KeySource>,
+}
+
+#[derive(Debug, Default)]
+struct KeySet {
+    keys: Vec<Ed25519PublicKey>,
+    /// ids and subjects of the revoked tokens
+    revoked: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+struct KeySource {
+    /// inline key without key id
+    pk: Option<String>,
+    config: KeysConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub email: String,
+    /// roles granting the scopes configured in the policy
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// scopes granted to the user, e.g. `campaign:run`
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+const JWT_DURATION: u64 = 60 * 60 * 24 * 7;
+const JWT_ISS: &str = "crm_server";
+const JWT_AUD: &str = "crm";
+
+pub struct EncodingKey(Ed25519KeyPair);
+
+/// Claims of the issued tokens besides the user
+#[derive(Debug, Clone)]
+pub struct TokenOptions {
+    /// lifetime of the token in seconds
+    pub expiry: u64,
+    pub audience: String,
+    /// subject of the token, the email of the user if not set
+    pub subject: Option<String>,
+}
+
+impl Default for TokenOptions {
+    fn default() -> Self {
+        Self {
+            expiry: JWT_DURATION,
+            audience: JWT_AUD.to_string(),
+            subject: None,
+        }
+    }
+}
+
+impl EncodingKey {
+    pub fn load(pem: &str) -> Result<Self, jwt_simple::Error> {
+        Ok(Self(Ed25519KeyPair::from_pem(pem)?))
+    }
+
+    /// Load the key from a pem file
+    pub fn from_file(path: &Path) -> Result<Self, jwt_simple::Error> {
+        let pem = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::load(&pem)
+    }
+
+    /// A new random key
+    pub fn generate() -> Self {
+        Self(Ed25519KeyPair::generate())
+    }
+
+    /// The pem of the private key
+    pub fn to_pem(&self) -> String {
+        self.0.to_pem()
+    }
+
+    /// Set the key id of the tokens, which selects the public key verifying them
+    pub fn with_key_id(self, kid: &str) -> Self {
+        Self(self.0.with_key_id(kid))
+    }
+
+    pub fn sign(&self, user: impl Into<User>) -> Result<String, jwt_simple::Error> {
+        self.issue(user, &TokenOptions::default())
+    }
+
+    /// Sign a token with the given options, the token has a random id which can
+    /// be put in the deny-list to revoke it
+    pub fn issue(
+        &self,
+        user: impl Into<User>,
+        opts: &TokenOptions,
+    ) -> Result<String, jwt_simple::Error> {
+        let user = user.into();
+        let subject = opts.subject.clone().unwrap_or_else(|| user.email.clone());
+        let claims = Claims::with_custom_claims(user, Duration::from_secs(opts.expiry))
+            .with_issuer(JWT_ISS)
+            .with_audience(&opts.audience)
+            .with_subject(subject)
+            .with_jwt_id(token_id());
+        self.0.sign(claims)
+    }
+
+    pub fn public_key(&self) -> Ed25519PublicKey {
+        self.0.public_key()
+    }
+}
+
+impl DecodingKey {
+    pub fn load(pem: &str) -> Result<Self, jwt_simple::Error> {
+        Self::from_config(Some(pem), &KeysConfig::default())
+    }
+
+    /// Load the inline key if any and the keys of the files
+    pub fn from_config(pk: Option<&str>, config: &KeysConfig) -> Result<Self, jwt_simple::Error> {
+        let source = KeySource {
+            pk: pk.map(str::to_string),
+            config: config.clone(),
+        };
+        let keys = source.load()?;
+        Ok(Self {
+            keys: Arc::new(RwLock::new(keys)),
+            source: Arc::new(source),
+        })
+    }
+
+    /// Reload the keys and the deny-list, the current ones are kept if any file is invalid
+    pub fn reload(&self) -> Result<(), jwt_simple::Error> {
+        let keys = self.source.load()?;
+        let kids: Vec<_> = keys.keys.iter().map(|k| k.key_id().clone()).collect();
+        let revoked = keys.revoked.len();
+        *self.keys.write().map_err(|e| anyhow!("{e}"))? = keys;
+        info!("public keys reloaded: {:?}, {} revoked", kids, revoked);
+        Ok(())
+    }
+
+    /// Reload the keys whenever their files change, none if no file is configured
+    pub fn spawn_reload(&self) -> Option<JoinHandle<()>> {
+        let config = &self.source.config;
+        if config.dir.is_none() && config.jwks.is_none() && config.deny_list.is_none() {
+            return None;
+        }
+
+        let key = self.clone();
+        let interval = StdDuration::from_secs(config.reload_interval.max(1));
+        // taken now, so that the changes before the task first runs are reloaded
+        let mut fingerprint = config.fingerprint();
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let current = key.source.config.fingerprint();
+                if current == fingerprint {
+                    continue;
+                }
+                fingerprint = current;
+                if let Err(e) = key.reload() {
+                    warn!("failed to reload public keys: {e:#}");
+                }
+            }
+        }))
+    }
+
+    pub fn verify(&self, token: &str) -> Result<User, jwt_simple::Error> {
+        let opts = VerificationOptions {
+            allowed_issuers: Some(HashSet::from_strings(&[JWT_ISS])),
+            allowed_audiences: Some(HashSet::from_strings(&[JWT_AUD])),
+            ..Default::default()
+        };
+
+        let kid = Token::decode_metadata(token)?.key_id().map(str::to_string);
+        let keys = self.keys.read().map_err(|e| anyhow!("{e}"))?;
+        let mut ret = Err(anyhow!("unknown key id: {:?}", kid));
+        // the tokens without key id are verified by any key
+        for key in keys
+            .keys
+            .iter()
+            .filter(|k| kid.is_none() || *k.key_id() == kid)
+        {
+            ret = key.verify_token::<User>(token, Some(opts.clone()));
+            if ret.is_ok() {
+                break;
+            }
+        }
+
+        let claims = ret?;
+        let revoked = [&claims.jwt_id, &claims.subject]
+            .into_iter()
+            .flatten()
+            .any(|id| keys.revoked.contains(id));
+        if revoked {
+            bail!("token revoked");
+        }
+        Ok(claims.custom)
+    }
+}
+
+impl KeySource {
+    fn load(&self) -> Result<KeySet, jwt_simple::Error> {
+        let mut keys = vec![];
+        if let Some(pem) = &self.pk {
+            keys.push(Ed25519PublicKey::from_pem(pem)?);
+        }
+        keys.extend(self.config.load()?);
+        if keys.is_empty() {
+            bail!("no public key configured, set auth.pk or auth.keys");
+        }
+        let revoked = self.config.load_deny_list()?;
+        Ok(KeySet { keys, revoked })
+    }
+}
+
+/// A random token id
+fn token_id() -> String {
+    let mut id = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut id);
+    Hex::encode_to_string(id).expect("token id is encodable")
+}
+
+/// Why the bearer token of a request is rejected
+#[derive(Debug, thiserror::Error)]
+pub enum Unauthenticated {
+    #[error("missing token")]
+    MissingToken,
+
+    #[error("invalid token format")]
+    InvalidFormat,
+
+    #[error("{0}")]
+    InvalidToken(String),
+}
+
+impl From<Unauthenticated> for Status {
+    fn from(e: Unauthenticated) -> Self {
+        Status::unauthenticated(e.to_string())
+    }
+}
+
+/// Verify the bearer token of every request, the authenticated user is inserted
+/// into the request extensions
+impl Interceptor for DecodingKey {
+    fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+        let authorization = req
+            .metadata()
+            .get(AUTHORIZATION)
+            .map(|v| v.to_str().unwrap_or_default());
+        let user = self.authenticate(authorization)?;
+
+        req.extensions_mut().insert(user);
+        Ok(req)
+    }
+}
+
+impl DecodingKey {
+    /// The user of the bearer token in the given authorization value
+    pub fn authenticate(&self, authorization: Option<&str>) -> Result<User, Unauthenticated> {
+        let Some(authorization) = authorization else {
+            return Err(Unauthenticated::MissingToken);
+        };
+        let token = authorization
+            .strip_prefix(BEARER)
+            .ok_or(Unauthenticated::InvalidFormat)?;
+        let user = self
+            .verify(token)
+            .map_err(|e| Unauthenticated::InvalidToken(e.to_string()))?;
+        debug!("authenticated user: {:?}", user);
+        Ok(user)
+    }
+}
+
+/// The bearer token in the authorization metadata of a request
+pub fn bearer_token<T>(req: &Request<T>) -> Option<&str> {
+    req.metadata()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix(BEARER))
+}
+
+/// Attach a bearer token to the authorization metadata of a request
+pub fn set_bearer_token<T>(req: &mut Request<T>, token: &str) -> Result<(), InvalidMetadataValue> {
+    let value = MetadataValue::try_from(format!("{BEARER}{token}"))?;
+    req.metadata_mut().insert(AUTHORIZATION, value);
+    Ok(())
+}
+
+/// A trailers-only response of the given status
+pub fn status_response<B: Default>(status: Status) -> http::Response<B> {
+    let mut res = http::Response::new(B::default());
+    let headers = res.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+    if let Err(status) = status.add_header(headers) {
+        // the message cannot be encoded, send the code only
+        let _ = Status::new(status.code(), "").add_header(headers);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> User {
+        User {
+            name: "John Doe".to_string(),
+            email: "john.doe@example.com".to_string(),
+            roles: vec![],
+            scopes: vec!["campaign:run".to_string()],
+        }
+    }
+
+    /// A new key and the pem of its public key
+    fn keys() -> (EncodingKey, String) {
+        let ek = EncodingKey::generate();
+        let pk = ek.public_key().to_pem();
+        (ek, pk)
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let (ek, pk) = keys();
+        let token = ek.sign(user()).unwrap();
+        let dk = DecodingKey::load(&pk).unwrap();
+        let ret = dk.verify(&token).unwrap();
+        assert_eq!(ret, user());
+    }
+
+    #[test]
+    fn interceptor_should_insert_authenticated_user() {
+        let (ek, pk) = keys();
+        let token = ek.sign(user()).unwrap();
+        let mut dk = DecodingKey::load(&pk).unwrap();
+
+        let mut req = Request::new(());
+        set_bearer_token(&mut req, &token).unwrap();
+        let req = dk.call(req).unwrap();
+        assert_eq!(req.extensions().get::<User>(), Some(&user()));
+
+        let err = dk.call(Request::new(())).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+        let mut req = Request::new(());
+        req.metadata_mut()
+            .insert(AUTHORIZATION, token.parse().unwrap());
+        let err = dk.call(req).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    }
+
+    fn key_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("crm-keys-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rotated_keys_should_be_reloaded() {
+        let dir = key_dir("rotate");
+        let k1 = Ed25519KeyPair::generate().with_key_id("k1");
+        let k2 = Ed25519KeyPair::generate().with_key_id("k2");
+        std::fs::write(dir.join("k1.pem"), k1.public_key().to_pem()).unwrap();
+        let t1 = EncodingKey(k1).sign(user()).unwrap();
+        let t2 = EncodingKey(k2.clone()).sign(user()).unwrap();
+
+        let config = KeysConfig {
+            dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        let dk = DecodingKey::from_config(None, &config).unwrap();
+        assert_eq!(dk.verify(&t1).unwrap(), user());
+        assert!(dk.verify(&t2).is_err());
+
+        std::fs::write(dir.join("k2.pem"), k2.public_key().to_pem()).unwrap();
+        std::fs::remove_file(dir.join("k1.pem")).unwrap();
+        dk.reload().unwrap();
+        assert_eq!(dk.verify(&t2).unwrap(), user());
+        assert!(dk.verify(&t1).is_err());
+
+        // a token of an unknown key id is not verified by the keys without id
+        let (ek, pk) = keys();
+        let dk = DecodingKey::from_config(Some(&pk), &config).unwrap();
+        let token = ek.with_key_id("k1").sign(user()).unwrap();
+        assert!(dk.verify(&token).is_err());
+    }
+
+    #[test]
+    fn jwks_keys_should_verify_tokens() {
+        let dir = key_dir("jwks");
+        let key = Ed25519KeyPair::generate().with_key_id("k3");
+        let jwks = Jwks {
+            keys: vec![Jwk::from_public_key(&key.public_key())],
+        };
+        let path = dir.join("jwks.json");
+        std::fs::write(&path, serde_json::to_string(&jwks).unwrap()).unwrap();
+
+        let config = KeysConfig {
+            jwks: Some(path),
+            ..Default::default()
+        };
+        let dk = DecodingKey::from_config(None, &config).unwrap();
+        let token = EncodingKey(key).sign(user()).unwrap();
+        assert_eq!(dk.verify(&token).unwrap(), user());
+    }
+
+    #[test]
+    fn revoked_tokens_should_be_rejected() {
+        let dir = key_dir("revoke");
+        let deny_list = dir.join("revoked");
+        let config = KeysConfig {
+            deny_list: Some(deny_list.clone()),
+            ..Default::default()
+        };
+        let (ek, pk) = keys();
+        let dk = DecodingKey::from_config(Some(&pk), &config).unwrap();
+        let token = ek.sign(user()).unwrap();
+        let other = ek
+            .issue(
+                user(),
+                &TokenOptions {
+                    subject: Some("service".to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(dk.verify(&token).is_ok());
+
+        let claims = Ed25519PublicKey::from_pem(&pk)
+            .unwrap()
+            .verify_token::<User>(&token, None)
+            .unwrap();
+        std::fs::write(&deny_list, format!("{}\n", claims.jwt_id.unwrap())).unwrap();
+        dk.reload().unwrap();
+        assert!(dk.verify(&token).is_err());
+        assert!(dk.verify(&other).is_ok());
+
+        // revoking a subject revokes all of its tokens
+        std::fs::write(&deny_list, "service\n").unwrap();
+        dk.reload().unwrap();
+        assert!(dk.verify(&token).is_ok());
+        assert!(dk.verify(&other).is_err());
+
+        let other_audience = ek
+            .issue(
+                user(),
+                &TokenOptions {
+                    audience: "other".to_string(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(dk.verify(&other_audience).is_err());
+    }
+
+    #[tokio::test]
+    async fn revocations_should_be_reloaded_in_the_background() {
+        let dir = key_dir("spawn-reload");
+        let deny_list = dir.join("revoked");
+        let (ek, pk) = keys();
+        let config = AuthConfig {
+            pk: Some(pk),
+            keys: KeysConfig {
+                deny_list: Some(deny_list.clone()),
+                reload_interval: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let dk = config.decoding_key().unwrap();
+        let token = ek.sign(user()).unwrap();
+        assert!(dk.verify(&token).is_ok());
+
+        std::fs::write(&deny_list, format!("{}\n", user().email)).unwrap();
+        let revoked = async {
+            while dk.verify(&token).is_ok() {
+                tokio::time::sleep(StdDuration::from_millis(100)).await;
+            }
+        };
+        tokio::time::timeout(StdDuration::from_secs(5), revoked)
+            .await
+            .expect("the revocation is reloaded");
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::future::{self, Either, Ready};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tonic::Status;
+use tower::{Layer, Service};
+use tracing::info;
+
+use super::{status_response, User};
+
+/// Scopes required to call the gRPC methods, the methods missing from the table
+/// can be called by any authenticated user.
+///
+/// ```yaml
+/// policy:
+///   roles:
+///     admin: [stats:raw]
+///   methods:
+///     /user_stat.UserStats/RawQuery: [stats:raw]
+///     # every method of the service
+///     /notification.Notification: [notify:send]
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Policy {
+    /// scopes granted by each role
+    #[serde(default)]
+    pub roles: HashMap<String, Vec<String>>,
+    /// scopes required by a method (`/package.Service/Method`) or by every method
+    /// of a service (`/package.Service`)
+    #[serde(default)]
+    pub methods: HashMap<String, Vec<String>>,
+}
+
+/// Why a call is denied by the [Policy]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Denied {
+    #[error("missing user")]
+    MissingUser,
+
+    #[error("missing scopes: {}", .0.join(", "))]
+    MissingScopes(Vec<String>),
+}
+
+impl Policy {
+    /// The scopes required to call the method of the given path
+    pub fn required(&self, path: &str) -> &[String] {
+        if let Some(scopes) = self.methods.get(path) {
+            return scopes;
+        }
+        path.rsplit_once('/')
+            .and_then(|(service, _)| self.methods.get(service))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The scopes of the user, including the ones granted by their roles
+    pub fn scopes<'a>(&'a self, user: &'a User) -> HashSet<&'a str> {
+        user.roles
+            .iter()
+            .filter_map(|role| self.roles.get(role))
+            .flatten()
+            .chain(user.scopes.iter())
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Check that the user holds every scope required by the method of the given path
+    pub fn authorize(&self, path: &str, user: Option<&User>) -> Result<(), Denied> {
+        let required = self.required(path);
+        if required.is_empty() {
+            return Ok(());
+        }
+        let Some(user) = user else {
+            return Err(Denied::MissingUser);
+        };
+
+        let scopes = self.scopes(user);
+        let missing: Vec<_> = required
+            .iter()
+            .filter(|s| !scopes.contains(s.as_str()))
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            info!("{} is denied {path}, missing {:?}", user.email, missing);
+            Err(Denied::MissingScopes(missing))
+        }
+    }
+}
+
+impl From<Denied> for Status {
+    fn from(denied: Denied) -> Self {
+        match denied {
+            Denied::MissingUser => Status::unauthenticated(denied.to_string()),
+            Denied::MissingScopes(_) => Status::permission_denied(denied.to_string()),
+        }
+    }
+}
+
+/// A layer enforcing the policy on the calls, it must be installed after the
+/// `DecodingKey` interceptor which puts the authenticated user in the extensions
+#[derive(Debug, Clone)]
+pub struct AuthzLayer {
+    policy: Arc<Policy>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Authz<S> {
+    inner: S,
+    policy: Arc<Policy>,
+}
+
+impl AuthzLayer {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy: Arc::new(policy),
+        }
+    }
+}
+
+impl<S> Layer<S> for AuthzLayer {
+    type Service = Authz<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Authz {
+            inner,
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for Authz<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<Ready<Result<Self::Response, Self::Error>>, S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<ReqBody>) -> Self::Future {
+        let user = req.extensions().get::<User>();
+        match self.policy.authorize(req.uri().path(), user) {
+            Ok(()) => Either::Right(self.inner.call(req)),
+            Err(denied) => Either::Left(future::ok(status_response(denied.into()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> Policy {
+        serde_yaml::from_str(
+            r#"
+roles:
+  admin: [stats:raw, notify:send]
+methods:
+  /user_stat.UserStats/RawQuery: [stats:raw]
+  /notification.Notification: [notify:send]
+"#,
+        )
+        .unwrap()
+    }
+
+    fn user(roles: &[&str], scopes: &[&str]) -> User {
+        User {
+            name: "John Doe".to_string(),
+            email: "john.doe@example.com".to_string(),
+            roles: roles.iter().map(|s| s.to_string()).collect(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn methods_should_require_scopes_of_the_policy() {
+        let policy = policy();
+        let raw = "/user_stat.UserStats/RawQuery";
+        let send = "/notification.Notification/Send";
+
+        assert!(policy.authorize("/user_stat.UserStats/Query", None).is_ok());
+        assert!(policy
+            .authorize(raw, Some(&user(&[], &["stats:raw"])))
+            .is_ok());
+        assert!(policy.authorize(send, Some(&user(&["admin"], &[]))).is_ok());
+
+        let err = policy
+            .authorize(send, Some(&user(&[], &["stats:raw"])))
+            .unwrap_err();
+        assert_eq!(err, Denied::MissingScopes(vec!["notify:send".to_string()]));
+        assert_eq!(Status::from(err).code(), tonic::Code::PermissionDenied);
+        let err = policy.authorize(raw, None).unwrap_err();
+        assert_eq!(Status::from(err).code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn denied_call_should_not_reach_the_service() {
+        let svc = tower::service_fn(|_: http::Request<()>| async {
+            Ok::<_, Status>(http::Response::new("called".to_string()))
+        });
+        let mut svc = AuthzLayer::new(policy()).layer(svc);
+
+        let mut req = http::Request::builder()
+            .uri("/notification.Notification/Send")
+            .body(())
+            .unwrap();
+        req.extensions_mut().insert(user(&[], &["stats:raw"]));
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.headers()["grpc-status"], "7");
+        assert_eq!(res.body(), "");
+
+        let mut req = http::Request::builder()
+            .uri("/notification.Notification/Send")
+            .body(())
+            .unwrap();
+        req.extensions_mut().insert(user(&[], &["notify:send"]));
+        let res = svc.call(req).await.unwrap();
+        assert_eq!(res.body(), "called");
+    }
+}
@@ -1,9 +1,10 @@
 use serde_json::json;
 use std::{error::Error as StdError, fmt::Display};
+use thiserror::Error;
+use tonic::Status;
 
 /// Alias for `async` and `anyhow` friendly dynamic error
 /// `Box<dyn std::error::Error + Send + Sync + 'static>`.
-#[allow(unused)]
 pub type BoxError = Box<dyn StdError + Send + Sync + 'static>;
 
 /// Extension methods for types implementing `std::error::Error`.
@@ -33,6 +34,23 @@ where
 
 impl<T> StdErrorExt for T where T: StdError {}
 
+/// An invalid field of a request, returned as `INVALID_ARGUMENT`
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{0}")]
+pub struct InvalidArgument(String);
+
+impl InvalidArgument {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+impl From<InvalidArgument> for Status {
+    fn from(e: InvalidArgument) -> Self {
+        Status::invalid_argument(e.0)
+    }
+}
+
 /// Log an error before structured logging, e.g. via Tokio Tracing, has been initialized in a
 /// similar structured way.
 pub fn log_error<T>(error: &T)
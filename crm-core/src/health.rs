@@ -0,0 +1,89 @@
+use std::{future::Future, time::Duration};
+
+use anyhow::{bail, Result};
+use tokio::task::JoinHandle;
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use tonic_health::{
+    pb::{health_check_response, health_client::HealthClient, HealthCheckRequest},
+    server::HealthReporter,
+    ServingStatus,
+};
+use tracing::{info, warn};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A dependency of a service, e.g. its database
+pub trait Ping: Clone + Send + Sync + 'static {
+    /// Check that the dependency is reachable
+    fn ping(&self) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Report the given services as serving while the dependency is reachable
+pub fn report_ping<P: Ping>(
+    reporter: HealthReporter,
+    services: Vec<&'static str>,
+    dependency: P,
+) -> JoinHandle<()> {
+    report_health(reporter, services, move || {
+        let dependency = dependency.clone();
+        async move { dependency.ping().await }
+    })
+}
+
+/// Report the given services as serving while the check of their dependencies passes,
+/// and not serving otherwise. The empty service name is the overall health of the server.
+pub fn report_health<F, Fut>(
+    mut reporter: HealthReporter,
+    services: Vec<&'static str>,
+    check: F,
+) -> JoinHandle<()>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<()>> + Send,
+{
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        let mut current = None;
+        loop {
+            ticker.tick().await;
+            let status = match check().await {
+                Ok(()) => ServingStatus::Serving,
+                Err(e) => {
+                    warn!("dependency check failed: {e:#}");
+                    ServingStatus::NotServing
+                }
+            };
+            if current == Some(status) {
+                continue;
+            }
+
+            info!("{:?} is {:?}", services, status);
+            for service in &services {
+                reporter.set_service_status(service, status).await;
+            }
+            current = Some(status);
+        }
+    })
+}
+
+/// Check that a service of the server at the given address is serving
+pub async fn check_serving(addr: &str, service: &str, tls: Option<&ClientTlsConfig>) -> Result<()> {
+    let mut endpoint = Endpoint::from_shared(addr.to_string())?
+        .connect_timeout(CHECK_TIMEOUT)
+        .timeout(CHECK_TIMEOUT);
+    if let Some(tls) = tls {
+        endpoint = endpoint.tls_config(tls.clone())?;
+    }
+    let channel = endpoint.connect().await?;
+    let res = HealthClient::new(channel)
+        .check(HealthCheckRequest {
+            service: service.to_string(),
+        })
+        .await?;
+    let status = res.into_inner().status();
+    if status != health_check_response::ServingStatus::Serving {
+        bail!("{service} at {addr} is {}", status.as_str_name());
+    }
+    Ok(())
+}
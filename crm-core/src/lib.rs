@@ -1,11 +1,18 @@
+pub mod auth;
 mod config;
 mod error;
+pub mod health;
+pub mod metrics;
 mod otel;
 pub mod telemetry;
+mod tls;
 
 pub use config::ConfigExt;
-pub use error::log_error;
-pub use otel::{accept_trace, make_span, SendTrace};
+pub use error::{log_error, InvalidArgument};
+pub use otel::{
+    accept_headers_trace, accept_trace, make_span, redact_headers, trace_headers, SendTrace,
+};
+pub use tls::TlsConfig;
 use tokio::signal;
 use tracing::info;
 
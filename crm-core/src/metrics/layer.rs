@@ -0,0 +1,245 @@
+use std::{
+    collections::HashSet,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use futures::future::BoxFuture;
+use http::HeaderMap;
+use http_body::{Body, Frame, SizeHint};
+use prost::Message;
+use prost_types::FileDescriptorSet;
+use tonic::{Code, Status};
+use tower::{Layer, Service};
+
+use super::record_request;
+use crate::error::BoxError;
+
+const GRPC_STATUS: &str = "grpc-status";
+/// the label of the paths which are not methods of the server
+const UNKNOWN_METHOD: &str = "unknown";
+
+/// A layer recording the count, the latency and the status of the gRPC calls
+/// by method. The status is read from the trailers of the response, or from its
+/// headers if trailers-only, so the call is recorded once its body is sent.
+#[derive(Debug, Clone)]
+pub struct GrpcMetricsLayer {
+    methods: Arc<HashSet<String>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GrpcMetrics<S> {
+    inner: S,
+    methods: Arc<HashSet<String>>,
+}
+
+/// The body of a response, recording the call at its trailers
+pub struct GrpcMetricsBody<B> {
+    inner: Pin<Box<B>>,
+    call: Option<Call>,
+}
+
+/// A call pending its status
+struct Call {
+    method: String,
+    start: Instant,
+}
+
+impl GrpcMetricsLayer {
+    /// Label the calls by the methods of the given encoded file descriptor sets
+    /// and of the health service. The other paths share a single label, so that
+    /// the clients cannot create series with arbitrary paths.
+    pub fn new(file_descriptor_sets: &[&[u8]]) -> Result<Self, prost::DecodeError> {
+        let mut methods = HashSet::new();
+        for set in [tonic_health::pb::FILE_DESCRIPTOR_SET]
+            .iter()
+            .chain(file_descriptor_sets)
+        {
+            for file in FileDescriptorSet::decode(*set)?.file {
+                let package = file.package();
+                for service in &file.service {
+                    let name = match package {
+                        "" => service.name().to_string(),
+                        package => format!("{package}.{}", service.name()),
+                    };
+                    methods.extend(
+                        service
+                            .method
+                            .iter()
+                            .map(|method| format!("/{name}/{}", method.name())),
+                    );
+                }
+            }
+        }
+        Ok(Self {
+            methods: Arc::new(methods),
+        })
+    }
+}
+
+impl<S> Layer<S> for GrpcMetricsLayer {
+    type Service = GrpcMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcMetrics {
+            inner,
+            methods: self.methods.clone(),
+        }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for GrpcMetrics<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError>,
+{
+    type Response = http::Response<GrpcMetricsBody<ResBody>>;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<ReqBody>) -> Self::Future {
+        let path = req.uri().path();
+        let method = match self.methods.contains(path) {
+            true => path,
+            false => UNKNOWN_METHOD,
+        };
+        let call = Call {
+            method: method.to_string(),
+            start: Instant::now(),
+        };
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            match fut.await {
+                Ok(res) => match grpc_status(res.headers()) {
+                    // trailers-only
+                    Some(status) => {
+                        call.record(status);
+                        Ok(res.map(|body| GrpcMetricsBody::new(body, None)))
+                    }
+                    None => Ok(res.map(|body| GrpcMetricsBody::new(body, Some(call)))),
+                },
+                Err(e) => {
+                    let e = e.into();
+                    let code = e
+                        .downcast_ref::<Status>()
+                        .map_or(Code::Unknown, Status::code);
+                    call.record(&(code as i32).to_string());
+                    Err(e)
+                }
+            }
+        })
+    }
+}
+
+impl<B> GrpcMetricsBody<B> {
+    fn new(body: B, call: Option<Call>) -> Self {
+        Self {
+            inner: Box::pin(body),
+            call,
+        }
+    }
+
+    fn record(&mut self, code: Code) {
+        if let Some(call) = self.call.take() {
+            call.record(&(code as i32).to_string());
+        }
+    }
+}
+
+impl<B: Body> Body for GrpcMetricsBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = self.inner.as_mut().poll_frame(cx);
+        match &frame {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(trailers) = frame.trailers_ref() {
+                    if let Some(call) = self.call.take() {
+                        call.record(grpc_status(trailers).unwrap_or("unknown"));
+                    }
+                }
+            }
+            Poll::Ready(Some(Err(_))) => self.record(Code::Unknown),
+            // ended without trailers
+            Poll::Ready(None) => self.record(Code::Unknown),
+            Poll::Pending => {}
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for GrpcMetricsBody<B> {
+    /// The client went away before the end of the response
+    fn drop(&mut self) {
+        self.record(Code::Cancelled);
+    }
+}
+
+impl Call {
+    fn record(self, grpc_status: &str) {
+        record_request(&self.method, grpc_status, self.start.elapsed());
+    }
+}
+
+fn grpc_status(headers: &HeaderMap) -> Option<&str> {
+    headers.get(GRPC_STATUS).and_then(|v| v.to_str().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::status_response;
+    use crate::metrics::REQUESTS;
+
+    #[tokio::test]
+    async fn calls_should_be_recorded_with_their_status() {
+        const CHECK: &str = "/grpc.health.v1.Health/Check";
+        const WATCH: &str = "/grpc.health.v1.Health/Watch";
+        let svc = tower::service_fn(|req: http::Request<()>| async move {
+            match req.uri().path() {
+                CHECK => Ok(status_response(Status::unauthenticated("no token"))),
+                WATCH => Ok(http::Response::new(tonic::body::empty_body())),
+                _ => Err(Status::unavailable("busy")),
+            }
+        });
+        let mut svc = GrpcMetricsLayer::new(&[]).unwrap().layer(svc);
+        let count = |method, status| REQUESTS.with_label_values(&[method, status]).get();
+
+        let req = |path| http::Request::builder().uri(path).body(()).unwrap();
+        let res = svc.call(req(CHECK)).await.unwrap();
+        assert_eq!(count(CHECK, "16"), 1);
+        drop(res);
+        assert_eq!(count(CHECK, "1"), 0);
+
+        // the response is dropped before its trailers
+        let res = svc.call(req(WATCH)).await.unwrap();
+        assert_eq!(count(WATCH, "1"), 0);
+        drop(res);
+        assert_eq!(count(WATCH, "1"), 1);
+
+        // the paths which are not methods of the server share a label
+        assert!(svc.call(req("/crm.Crm/Remind")).await.is_err());
+        assert!(svc.call(req("/random/path")).await.is_err());
+        assert_eq!(count("/crm.Crm/Remind", "14"), 0);
+        assert_eq!(count(UNKNOWN_METHOD, "14"), 2);
+    }
+}
@@ -0,0 +1,72 @@
+mod layer;
+
+use std::{
+    io,
+    net::{SocketAddr, TcpListener},
+    sync::LazyLock,
+    time::Duration,
+};
+
+use axum::{http::header::CONTENT_TYPE, response::IntoResponse, routing::get, Router};
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, Encoder, HistogramVec, IntCounterVec,
+    TextEncoder,
+};
+use tracing::{info, warn};
+
+pub use layer::{GrpcMetrics, GrpcMetricsBody, GrpcMetricsLayer};
+
+static REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "grpc_server_requests_total",
+        "Requests served by method and gRPC status",
+        &["method", "grpc_status"]
+    )
+    .expect("metric is registered once")
+});
+
+static LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "grpc_server_request_duration_seconds",
+        "Latency of the served requests by method, until the end of their response",
+        &["method"]
+    )
+    .expect("metric is registered once")
+});
+
+/// Record a request served by the service
+fn record_request(method: &str, grpc_status: &str, latency: Duration) {
+    REQUESTS.with_label_values(&[method, grpc_status]).inc();
+    LATENCY
+        .with_label_values(&[method])
+        .observe(latency.as_secs_f64());
+}
+
+/// Serve the metrics of the default registry at `/metrics` on the address, the
+/// address is bound before returning so that a busy port fails the start
+pub(crate) fn serve(addr: SocketAddr) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    info!("Metrics served on http://{addr}/metrics");
+
+    let app = Router::new().route("/metrics", get(metrics));
+    tokio::spawn(async move {
+        let served = async {
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            axum::serve(listener, app).await
+        };
+        if let Err(e) = served.await {
+            warn!("failed to serve the metrics: {e}");
+        }
+    });
+    Ok(())
+}
+
+async fn metrics() -> impl IntoResponse {
+    let encoder = TextEncoder::new();
+    let mut buf = vec![];
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buf) {
+        warn!("failed to encode the metrics: {e}");
+    }
+    ([(CONTENT_TYPE, encoder.format_type().to_string())], buf)
+}
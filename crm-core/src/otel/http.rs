@@ -1,27 +1,77 @@
-use http::{HeaderMap, Request};
-use opentelemetry::{global, propagation::Extractor, trace::TraceContextExt};
+use http::{
+    header::{AUTHORIZATION, COOKIE},
+    HeaderMap, HeaderName, HeaderValue, Request,
+};
+use opentelemetry::{
+    global,
+    propagation::{Extractor, Injector},
+    trace::TraceContextExt,
+};
 use tracing::{field, info_span, warn, Span};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+const REDACTED: HeaderValue = HeaderValue::from_static("[redacted]");
+
 /// Create a span for the incoming request
 pub fn make_span<B>(request: &http::Request<B>) -> Span {
-    let headers = request.headers();
+    let headers = redact_headers(request.headers());
     info_span!("incoming request", ?headers, trace_id = field::Empty)
 }
 
 /// Trace context propagation: associate the current span with the OTel trace of the given request
 pub fn accept_trace<B>(request: &Request<B>, span: &Span) {
-    let parent_context = global::get_text_map_propagator(|propagator| {
-        propagator.extract(&HeaderExtractor(request.headers()))
-    });
+    accept_headers_trace(request.headers(), span)
+}
+
+/// Associate the span with the OTel trace of the given headers, the `trace_id`
+/// field of the span is recorded
+pub fn accept_headers_trace(headers: &HeaderMap, span: &Span) {
+    let parent_context =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
     span.set_parent(parent_context);
 
     let trace_id = span.context().span().span_context().trace_id();
     span.record("trace_id", trace_id.to_string());
 }
 
+/// The headers propagating the trace context of the span, e.g. `traceparent`
+pub fn trace_headers(span: &Span) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&span.context(), &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+/// A copy of the headers without the credentials, to be logged
+pub fn redact_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut headers = headers.clone();
+    for name in [AUTHORIZATION, COOKIE] {
+        if headers.contains_key(&name) {
+            headers.insert(name, REDACTED);
+        }
+    }
+    headers
+}
+
 struct HeaderExtractor<'a>(&'a HeaderMap);
 
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        match (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            (Ok(name), Ok(value)) => {
+                self.0.insert(name, value);
+            }
+            _ => warn!(key, value, "invalid trace header"),
+        }
+    }
+}
+
 impl Extractor for HeaderExtractor<'_> {
     fn get(&self, key: &str) -> Option<&str> {
         self.0.get(key).and_then(|v| {
@@ -37,3 +87,20 @@ impl Extractor for HeaderExtractor<'_> {
         self.0.keys().map(|k| k.as_str()).collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_should_be_redacted() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        headers.insert("x-request-id", HeaderValue::from_static("42"));
+
+        let redacted = redact_headers(&headers);
+        assert_eq!(redacted[AUTHORIZATION], "[redacted]");
+        assert_eq!(redacted["x-request-id"], "42");
+        assert!(!format!("{redacted:?}").contains("secret"));
+    }
+}
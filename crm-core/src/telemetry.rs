@@ -1,23 +1,145 @@
-use crate::error::StdErrorExt;
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
+use crate::{error::StdErrorExt, metrics};
 use opentelemetry::{trace::TracerProvider, KeyValue};
-use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
+use opentelemetry_otlp::{TonicExporterBuilder, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{self, Sampler},
+    Resource,
+};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tonic::metadata::MetadataMap;
 use tracing::{debug, error};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::{
+    filter::filter_fn, fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+};
+
+/// Targets of the events of the OTLP exporter itself, which are not exported as
+/// logs not to feed the exporter with its own logs
+const EXPORTER_TARGETS: &[&str] = &["opentelemetry", "tonic", "h2", "hyper", "tower"];
 
 /// Telemetry (logging, tracing, metrics) configuration.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
     #[serde(rename = "tracing")]
     pub tracing_config: TracingConfig,
+    #[serde(default)]
+    pub logs: LogsConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
 }
 
 /// Tracing (as opposed to logging or metrics) configuration.
+///
+/// ```yaml
+/// tracing:
+///   enabled: true
+///   service_name: crm
+///   endpoint: http://localhost:4317
+///   headers:
+///     x-api-key: secret
+///   timeout: 10
+///   sampling:
+///     ratio: 0.1
+///     parent_based: true
+///   resource:
+///     deployment.environment: dev
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct TracingConfig {
     pub enabled: bool,
     pub service_name: String,
+    /// OTLP gRPC endpoint of the collector, `http://localhost:4317` if not set
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// metadata sent with each export, e.g. the credentials of the collector
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// timeout of an export in seconds
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub sampling: SamplingConfig,
+    /// attributes of the resource besides `service.name`
+    #[serde(default)]
+    pub resource: HashMap<String, String>,
+}
+
+/// Sampling of the traces
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplingConfig {
+    /// ratio of the traces sampled, from 0 to 1
+    pub ratio: f64,
+    /// sample the spans with a parent if and only if their parent is sampled, the
+    /// ratio applies to the root spans only
+    pub parent_based: bool,
+}
+
+/// Logging configuration, the logs are exported to the collector of the traces.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct LogsConfig {
+    pub format: LogFormat,
+    /// export the logs over OTLP besides writing them to stdout
+    pub export: bool,
+}
+
+/// Format of the logs written to stdout
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Json,
+    /// multi-line human readable logs, for development
+    Pretty,
+}
+
+/// Metrics configuration, served to Prometheus at `/metrics` on the address.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub addr: SocketAddr,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            ratio: 1.0,
+            parent_based: true,
+        }
+    }
+}
+
+impl SamplingConfig {
+    fn sampler(&self) -> Sampler {
+        let sampler = if self.ratio >= 1.0 {
+            Sampler::AlwaysOn
+        } else if self.ratio <= 0.0 {
+            Sampler::AlwaysOff
+        } else {
+            Sampler::TraceIdRatioBased(self.ratio)
+        };
+        if self.parent_based {
+            Sampler::ParentBased(Box::new(sampler))
+        } else {
+            sampler
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            addr: ([127, 0, 0, 1], 9090).into(),
+        }
+    }
 }
 
 /// Error possibly returned by [init].
@@ -29,61 +151,134 @@ pub enum Error {
     #[error("cannot initialize tracing subscriber")]
     TryInit(#[from] tracing_subscriber::util::TryInitError),
 
+    #[error("invalid OTLP headers")]
+    InvalidHeaders(#[from] http::Error),
+
     #[error("cannot install OTLP tracer")]
     InstallOtlpTracer(#[from] opentelemetry::trace::TraceError),
+
+    #[error("cannot install OTLP logger")]
+    InstallOtlpLogger(#[from] opentelemetry::logs::LogError),
+
+    #[error("cannot serve metrics")]
+    ServeMetrics(#[from] std::io::Error),
 }
 
 /// Initialize telemetry
 ///
 /// Apply an `EnvFilter` using the `RUST_LOG` environment variable to define
-/// the log levels, add a formatter layer logging as JSON and an OpenTelemetry layer
-/// exporting tracing data if tracing is enabled.
+/// the log levels, add a formatter layer logging as JSON or pretty text, an
+/// OpenTelemetry layer exporting tracing data if tracing is enabled and one
+/// exporting the logs if enabled, and serve the metrics if enabled.
 pub fn init(config: Config) -> Result<(), Error> {
-    let Config { tracing_config } = config;
+    let Config {
+        tracing_config,
+        logs,
+        metrics: metrics_config,
+    } = config;
 
-    let tracing = tracing_subscriber::registry()
-        .with(EnvFilter::from_default_env())
-        .with(tracing_subscriber::fmt::layer().json().flatten_event(true));
-
-    if tracing_config.enabled {
-        debug!("tracing enabled");
-        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    let (json, pretty) = match logs.format {
+        LogFormat::Json => (Some(fmt::layer().json().flatten_event(true)), None),
+        LogFormat::Pretty => (None, Some(fmt::layer().pretty())),
+    };
 
+    if tracing_config.enabled || logs.export {
         opentelemetry::global::set_error_handler(|error| {
             error!(error = error.as_chain(), target = "otel", "otel error")
         })?;
+    }
 
-        tracing.with(otlp_layer(tracing_config)?).try_init()?
+    let traces = if tracing_config.enabled {
+        debug!("tracing enabled");
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        Some(otlp_layer(&tracing_config)?)
+    } else {
+        None
+    };
+    let logs = if logs.export {
+        Some(otlp_logs_layer(&tracing_config)?)
     } else {
-        tracing.try_init()?
+        None
+    };
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(json)
+        .with(pretty)
+        .with(traces)
+        .with(logs)
+        .try_init()?;
+
+    if metrics_config.enabled {
+        metrics::serve(metrics_config.addr)?;
     }
 
     Ok(())
 }
 
 /// Create an OTLP layer exporting tracing data.
-fn otlp_layer<S>(config: TracingConfig) -> Result<impl tracing_subscriber::Layer<S>, Error>
+fn otlp_layer<S>(config: &TracingConfig) -> Result<impl Layer<S>, Error>
 where
     S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
 {
-    let exporter = opentelemetry_otlp::new_exporter().tonic();
-
-    let service_name = Resource::new(vec![KeyValue::new(
-        "service.name",
-        config.service_name.clone(),
-    )]);
-    let trace_config = trace::Config::default().with_resource(service_name);
+    let trace_config = trace::Config::default()
+        .with_sampler(config.sampling.sampler())
+        .with_resource(resource(config));
 
     let tracer = opentelemetry_otlp::new_pipeline()
         .tracing()
-        .with_exporter(exporter)
+        .with_exporter(exporter(config)?)
         .with_trace_config(trace_config)
         .install_batch(runtime::Tokio)?
-        .tracer(config.service_name);
+        .tracer(config.service_name.clone());
 
     Ok(tracing_opentelemetry::layer().with_tracer(tracer))
 }
 
+/// Create an OTLP layer exporting the events as logs.
+fn otlp_logs_layer<S>(config: &TracingConfig) -> Result<impl Layer<S>, Error>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    let provider = opentelemetry_otlp::new_pipeline()
+        .logging()
+        .with_resource(resource(config))
+        .with_exporter(exporter(config)?)
+        .install_batch(runtime::Tokio)?;
+
+    Ok(
+        OpenTelemetryTracingBridge::new(&provider).with_filter(filter_fn(|meta| {
+            !EXPORTER_TARGETS
+                .iter()
+                .any(|target| meta.target().starts_with(target))
+        })),
+    )
+}
+
+/// The OTLP exporter to the collector of the config
+fn exporter(config: &TracingConfig) -> Result<TonicExporterBuilder, Error> {
+    let mut exporter = opentelemetry_otlp::new_exporter().tonic();
+    if let Some(endpoint) = &config.endpoint {
+        exporter = exporter.with_endpoint(endpoint);
+    }
+    if let Some(timeout) = config.timeout {
+        exporter = exporter.with_timeout(Duration::from_secs(timeout));
+    }
+    if !config.headers.is_empty() {
+        let headers = http::HeaderMap::try_from(&config.headers)?;
+        exporter = exporter.with_metadata(MetadataMap::from_headers(headers));
+    }
+    Ok(exporter)
+}
+
+fn resource(config: &TracingConfig) -> Resource {
+    let attributes = config
+        .resource
+        .iter()
+        .map(|(key, value)| KeyValue::new(key.clone(), value.clone()));
+    Resource::new(attributes.chain([KeyValue::new("service.name", config.service_name.clone())]))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::telemetry::{self, Config, TracingConfig};
@@ -93,17 +288,55 @@ mod tests {
         let tracing_config = TracingConfig {
             enabled: true,
             service_name: "test".to_string(),
+            ..Default::default()
+        };
+        let config = Config {
+            tracing_config,
+            ..Default::default()
         };
-        let config = Config { tracing_config };
         let result = telemetry::init(config);
         assert!(result.is_ok());
 
         let tracing_config = TracingConfig {
             enabled: false,
             service_name: "test".to_string(),
+            ..Default::default()
+        };
+        let config = Config {
+            tracing_config,
+            ..Default::default()
         };
-        let config = Config { tracing_config };
         let result = telemetry::init(config);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn tracing_config_should_configure_sampler_and_resource() {
+        let config: TracingConfig = serde_yaml::from_str(
+            r#"
+enabled: true
+service_name: crm
+sampling:
+  ratio: 0.25
+resource:
+  deployment.environment: dev
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            format!("{:?}", config.sampling.sampler()),
+            "ParentBased(TraceIdRatioBased(0.25))"
+        );
+        let resource = telemetry::resource(&config);
+        assert_eq!(resource.len(), 2);
+        assert_eq!(resource.get("service.name".into()).unwrap().as_str(), "crm");
+
+        let err = telemetry::exporter(&TracingConfig {
+            headers: [("x api key".to_string(), "secret".to_string())].into(),
+            ..config
+        })
+        .err()
+        .unwrap();
+        assert_eq!(err.to_string(), "invalid OTLP headers");
+    }
 }
@@ -0,0 +1,93 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+
+/// TLS of a service: the certificate it serves and presents to the services it
+/// calls, and the CA the certificates of its peers are verified with.
+///
+/// ```yaml
+/// tls:
+///   cert: /etc/crm/tls/server.crt
+///   key: /etc/crm/tls/server.key
+///   # require client certificates signed by the CA and verify the servers called
+///   ca: /etc/crm/tls/ca.crt
+///   # name of the servers called, checked against their certificates
+///   domain: crm.internal
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// PEM certificate chain
+    pub cert: PathBuf,
+    /// PEM private key of the certificate
+    pub key: PathBuf,
+    /// PEM CA certificate of the peers, no client certificate is required if not set
+    pub ca: Option<PathBuf>,
+    /// domain name of the servers called, the host of their address if not set
+    pub domain: Option<String>,
+}
+
+impl TlsConfig {
+    /// The identity of the service, its certificate and key
+    pub fn identity(&self) -> Result<Identity> {
+        Ok(Identity::from_pem(read(&self.cert)?, read(&self.key)?))
+    }
+
+    /// The CA certificate of the peers
+    pub fn ca_certificate(&self) -> Result<Option<Certificate>> {
+        self.ca
+            .as_ref()
+            .map(|ca| Ok(Certificate::from_pem(read(ca)?)))
+            .transpose()
+    }
+
+    /// TLS of a server, the client certificates are verified if a CA is set
+    pub fn server_config(&self) -> Result<ServerTlsConfig> {
+        let mut config = ServerTlsConfig::new().identity(self.identity()?);
+        if let Some(ca) = self.ca_certificate()? {
+            config = config.client_ca_root(ca);
+        }
+        Ok(config)
+    }
+
+    /// TLS of a client presenting the certificate of the service
+    pub fn client_config(&self) -> Result<ClientTlsConfig> {
+        let mut config = ClientTlsConfig::new().identity(self.identity()?);
+        if let Some(ca) = self.ca_certificate()? {
+            config = config.ca_certificate(ca);
+        }
+        if let Some(domain) = &self.domain {
+            config = config.domain_name(domain);
+        }
+        Ok(config)
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CERTS: &str = "../load-balancer/assets/cert";
+
+    #[test]
+    fn tls_config_should_load_the_pem_files() {
+        let mut config: TlsConfig = serde_yaml::from_str(&format!(
+            "cert: {CERTS}/server.crt\nkey: {CERTS}/server.key\nca: {CERTS}/ca.crt\n"
+        ))
+        .unwrap();
+        assert!(config.server_config().is_ok());
+        assert!(config.client_config().is_ok());
+
+        config.ca = Some(PathBuf::from("missing.crt"));
+        let err = config.server_config().unwrap_err();
+        assert_eq!(err.to_string(), "failed to read missing.crt");
+    }
+}
@@ -6,6 +6,7 @@ fn main() -> Result<()> {
     let builder = tonic_build::configure();
     builder
         .out_dir("src/pb")
+        .file_descriptor_set_path("src/pb/metadata_descriptor.bin")
         .type_attribute("metadata.MaterializeRequest", r#"#[derive(Eq, Hash)]"#)
         .compile(
             &[
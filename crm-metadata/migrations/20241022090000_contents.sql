@@ -0,0 +1,36 @@
+-- Add migration script here
+CREATE TYPE content_type AS ENUM(
+  'unspecified',
+  'short',
+  'vlog',
+  'movie',
+  'ai_generated'
+);
+
+CREATE TABLE publishers(
+  id serial PRIMARY KEY,
+  name varchar(64) NOT NULL,
+  avatar varchar(256) NOT NULL DEFAULT '',
+  created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE contents(
+  id serial PRIMARY KEY,
+  name varchar(256) NOT NULL,
+  description text NOT NULL DEFAULT '',
+  url varchar(256) NOT NULL,
+  image varchar(256) NOT NULL DEFAULT '',
+  type content_type NOT NULL DEFAULT 'unspecified',
+  created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  views bigint NOT NULL DEFAULT 0,
+  likes bigint NOT NULL DEFAULT 0,
+  dislikes bigint NOT NULL DEFAULT 0
+);
+
+CREATE TABLE content_publishers(
+  content_id integer NOT NULL REFERENCES contents(id),
+  publisher_id integer NOT NULL REFERENCES publishers(id),
+  PRIMARY KEY (content_id, publisher_id)
+);
+
+CREATE INDEX content_publishers_publisher_id_idx ON content_publishers(publisher_id);
@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE publishers ADD COLUMN archived_at timestamptz;
+
+ALTER TABLE contents ADD COLUMN archived_at timestamptz;
+
+CREATE INDEX contents_type_idx ON contents(type) WHERE archived_at IS NULL;
+
+CREATE INDEX contents_search_idx ON contents USING GIN(to_tsvector('english', name || ' ' || description));
@@ -0,0 +1,261 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use lru::LruCache;
+use tokio::sync::RwLock;
+use tracing::{debug, instrument};
+
+use super::ContentRepo;
+use crate::{
+    config::CacheConfig,
+    metrics::record_cache_lookup,
+    pb::{
+        Content, ContentType, CreateContentRequest, CreatePublisherRequest, ListContentsRequest,
+        Publisher, UpdateContentRequest, UpdatePublisherRequest,
+    },
+};
+
+/// A repository caching the materialized contents of another one in an LRU cache.
+///
+/// The ids which are not found are cached as well, so that unknown ids do not hit the
+/// repository on every request. Entries are invalidated when the contents are changed
+/// through this repository, changes made by other instances are visible after the ttl.
+pub struct CachedRepo<R> {
+    repo: R,
+    cache: Mutex<LruCache<u32, Entry>>,
+    ttl: Duration,
+    /// held by the writes while they change the repository and invalidate the cache,
+    /// and by the misses while they load and cache the contents, so that a content
+    /// loaded before a write is not cached after it
+    writes: RwLock<()>,
+}
+
+/// A cached content, none if the id is not found
+struct Entry {
+    content: Option<Content>,
+    expires_at: Instant,
+}
+
+/// Cache lookup of a content id
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lookup {
+    Found(Content),
+    NotFound,
+    Miss,
+}
+
+impl<R: ContentRepo> CachedRepo<R> {
+    pub fn new(repo: R, config: &CacheConfig) -> Self {
+        let capacity = NonZeroUsize::new(config.capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            repo,
+            cache: Mutex::new(LruCache::new(capacity)),
+            ttl: Duration::from_secs(config.ttl),
+            writes: RwLock::new(()),
+        }
+    }
+
+    /// Look up a content in the cache, the expired entries are misses
+    pub fn lookup(&self, id: u32) -> Result<Lookup> {
+        let mut cache = self.cache.lock().map_err(|e| anyhow!("{e}"))?;
+        let ret = match cache.get(&id) {
+            Some(entry) if entry.expires_at > Instant::now() => match &entry.content {
+                Some(content) => Lookup::Found(content.clone()),
+                None => Lookup::NotFound,
+            },
+            Some(_) => {
+                cache.pop(&id);
+                Lookup::Miss
+            }
+            None => Lookup::Miss,
+        };
+        record_cache_lookup(ret != Lookup::Miss);
+        Ok(ret)
+    }
+
+    fn insert(&self, ids: &[u32], contents: &HashMap<u32, Content>) -> Result<()> {
+        let mut cache = self.cache.lock().map_err(|e| anyhow!("{e}"))?;
+        let expires_at = Instant::now() + self.ttl;
+        for id in ids {
+            let content = contents.get(id).cloned();
+            cache.put(
+                *id,
+                Entry {
+                    content,
+                    expires_at,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn invalidate(&self, id: u32) -> Result<()> {
+        let mut cache = self.cache.lock().map_err(|e| anyhow!("{e}"))?;
+        cache.pop(&id);
+        Ok(())
+    }
+
+    /// Publishers are embedded in the contents, any of which may be stale
+    fn invalidate_all(&self) -> Result<()> {
+        let mut cache = self.cache.lock().map_err(|e| anyhow!("{e}"))?;
+        cache.clear();
+        Ok(())
+    }
+}
+
+impl<R: ContentRepo> ContentRepo for CachedRepo<R> {
+    #[instrument(name = "materialize-cached", skip_all)]
+    async fn materialize(&self, ids: &[u32]) -> Result<Vec<Content>> {
+        let mut found = HashMap::new();
+        let mut missed = vec![];
+        for id in ids {
+            match self.lookup(*id)? {
+                Lookup::Found(content) => {
+                    found.insert(*id, content);
+                }
+                Lookup::NotFound => {}
+                Lookup::Miss => missed.push(*id),
+            }
+        }
+        debug!(
+            "{} cached, {} missed",
+            ids.len() - missed.len(),
+            missed.len()
+        );
+
+        if !missed.is_empty() {
+            let _read = self.writes.read().await;
+            let contents: HashMap<_, _> = self
+                .repo
+                .materialize(&missed)
+                .await?
+                .into_iter()
+                .map(|c| (c.id, c))
+                .collect();
+            self.insert(&missed, &contents)?;
+            found.extend(contents);
+        }
+
+        Ok(ids.iter().filter_map(|id| found.remove(id)).collect())
+    }
+
+    async fn create_content(&self, req: CreateContentRequest) -> Result<Content> {
+        let _write = self.writes.write().await;
+        let content = self.repo.create_content(req).await?;
+        // the id may be cached as not found
+        self.invalidate(content.id)?;
+        Ok(content)
+    }
+
+    async fn update_content(&self, req: UpdateContentRequest) -> Result<Option<Content>> {
+        let id = req.id;
+        let _write = self.writes.write().await;
+        let ret = self.repo.update_content(req).await;
+        self.invalidate(id)?;
+        ret
+    }
+
+    async fn archive_content(&self, id: u32) -> Result<Option<Content>> {
+        let _write = self.writes.write().await;
+        let ret = self.repo.archive_content(id).await;
+        self.invalidate(id)?;
+        ret
+    }
+
+    async fn create_publisher(&self, req: CreatePublisherRequest) -> Result<Publisher> {
+        self.repo.create_publisher(req).await
+    }
+
+    async fn update_publisher(&self, req: UpdatePublisherRequest) -> Result<Option<Publisher>> {
+        let _write = self.writes.write().await;
+        let ret = self.repo.update_publisher(req).await;
+        self.invalidate_all()?;
+        ret
+    }
+
+    async fn archive_publisher(&self, id: u32) -> Result<Option<Publisher>> {
+        let _write = self.writes.write().await;
+        let ret = self.repo.archive_publisher(id).await;
+        self.invalidate_all()?;
+        ret
+    }
+
+    async fn list_contents(&self, req: ListContentsRequest) -> Result<Vec<Content>> {
+        self.repo.list_contents(req).await
+    }
+
+    async fn candidates(
+        &self,
+        publisher_ids: &[u32],
+        types: &[ContentType],
+        exclude: &[u32],
+        limit: u32,
+    ) -> Result<Vec<Content>> {
+        self.repo
+            .candidates(publisher_ids, types, exclude, limit)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FakeRepo;
+
+    fn cached(ttl: u64) -> CachedRepo<FakeRepo> {
+        let config = CacheConfig { capacity: 16, ttl };
+        CachedRepo::new(FakeRepo::new(), &config)
+    }
+
+    #[tokio::test]
+    async fn materialized_contents_should_be_cached() {
+        let repo = cached(60);
+        assert_eq!(repo.lookup(1).unwrap(), Lookup::Miss);
+        let ret = repo.materialize(&[1, 2]).await.unwrap();
+
+        let Lookup::Found(content) = repo.lookup(1).unwrap() else {
+            panic!("content 1 should be cached");
+        };
+        assert_eq!(content, ret[0]);
+
+        // fabricated contents are random, a cached content is returned as is
+        let cached = repo.materialize(&[2, 1]).await.unwrap();
+        assert_eq!(cached, vec![ret[1].clone(), ret[0].clone()]);
+    }
+
+    #[tokio::test]
+    async fn expired_and_updated_contents_should_be_missed() {
+        let repo = cached(0);
+        repo.materialize(&[1]).await.unwrap();
+        assert_eq!(repo.lookup(1).unwrap(), Lookup::Miss);
+
+        let repo = cached(60);
+        let publisher = repo
+            .create_publisher(CreatePublisherRequest {
+                name: "alice".to_string(),
+                avatar: "".to_string(),
+            })
+            .await
+            .unwrap();
+        let content = repo
+            .create_content(CreateContentRequest {
+                name: "Rust".to_string(),
+                publisher_ids: vec![publisher.id],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        repo.materialize(&[content.id]).await.unwrap();
+        repo.archive_content(content.id).await.unwrap();
+        assert_eq!(repo.lookup(content.id).unwrap(), Lookup::Miss);
+
+        // archived contents are not found, and cached as such
+        assert!(repo.materialize(&[content.id]).await.unwrap().is_empty());
+        assert_eq!(repo.lookup(content.id).unwrap(), Lookup::NotFound);
+    }
+}
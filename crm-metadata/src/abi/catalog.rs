@@ -0,0 +1,300 @@
+use super::{ContentRepo, UnknownPublishers};
+use crate::{
+    pb::{
+        ArchiveContentRequest, ArchivePublisherRequest, Content, ContentType, CreateContentRequest,
+        CreatePublisherRequest, ListContentsRequest, Publisher, UpdateContentRequest,
+        UpdatePublisherRequest,
+    },
+    MetadataService, ServiceResult,
+};
+use crm_core::InvalidArgument;
+use futures::{stream, Stream};
+use tonic::{Response, Status};
+use tracing::{info, instrument};
+
+const DEFAULT_LIMIT: u32 = 20;
+const MAX_LIMIT: u32 = 100;
+
+impl<R: ContentRepo> MetadataService<R> {
+    #[instrument(name = "create-content", skip_all)]
+    pub async fn create_content(&self, req: CreateContentRequest) -> ServiceResult<Content> {
+        required("name", &req.name)?;
+        required("url", &req.url)?;
+        content_type(req.r#type)?;
+
+        let content = self.repo.create_content(req).await.map_err(to_status)?;
+        info!("Created content {}", content.id);
+
+        Ok(Response::new(content))
+    }
+
+    #[instrument(name = "update-content", skip_all)]
+    pub async fn update_content(&self, req: UpdateContentRequest) -> ServiceResult<Content> {
+        if let Some(name) = &req.name {
+            required("name", name)?;
+        }
+        if let Some(url) = &req.url {
+            required("url", url)?;
+        }
+        if let Some(r#type) = req.r#type {
+            content_type(r#type)?;
+        }
+
+        let id = req.id;
+        let content = self.repo.update_content(req).await.map_err(to_status)?;
+        content
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found(format!("Content {id} not found")))
+    }
+
+    /// Archive a content, it is not listed or materialized any more
+    #[instrument(name = "archive-content", skip_all)]
+    pub async fn archive_content(&self, req: ArchiveContentRequest) -> ServiceResult<Content> {
+        let content = self
+            .repo
+            .archive_content(req.id)
+            .await
+            .map_err(to_status)?
+            .ok_or_else(|| Status::not_found(format!("Content {} not found", req.id)))?;
+        info!("Archived content {}", req.id);
+
+        Ok(Response::new(content))
+    }
+
+    #[instrument(name = "create-publisher", skip_all)]
+    pub async fn create_publisher(&self, req: CreatePublisherRequest) -> ServiceResult<Publisher> {
+        required("name", &req.name)?;
+
+        let publisher = self.repo.create_publisher(req).await.map_err(to_status)?;
+        info!("Created publisher {}", publisher.id);
+
+        Ok(Response::new(publisher))
+    }
+
+    #[instrument(name = "update-publisher", skip_all)]
+    pub async fn update_publisher(&self, req: UpdatePublisherRequest) -> ServiceResult<Publisher> {
+        if let Some(name) = &req.name {
+            required("name", name)?;
+        }
+
+        let id = req.id;
+        let publisher = self.repo.update_publisher(req).await.map_err(to_status)?;
+        publisher
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found(format!("Publisher {id} not found")))
+    }
+
+    /// Archive a publisher, it can not be referred by contents any more
+    #[instrument(name = "archive-publisher", skip_all)]
+    pub async fn archive_publisher(
+        &self,
+        req: ArchivePublisherRequest,
+    ) -> ServiceResult<Publisher> {
+        let publisher = self
+            .repo
+            .archive_publisher(req.id)
+            .await
+            .map_err(to_status)?
+            .ok_or_else(|| Status::not_found(format!("Publisher {} not found", req.id)))?;
+        info!("Archived publisher {}", req.id);
+
+        Ok(Response::new(publisher))
+    }
+
+    /// List the contents filtered by type and publisher, and searched by `query`
+    #[instrument(name = "list-contents", skip_all)]
+    pub async fn list_contents(
+        &self,
+        mut req: ListContentsRequest,
+    ) -> ServiceResult<impl Stream<Item = Result<Content, Status>> + Send + 'static> {
+        if let Some(r#type) = req.r#type {
+            content_type(r#type)?;
+        }
+        req.query = req.query.trim().to_string();
+        req.limit = match req.limit {
+            0 => DEFAULT_LIMIT,
+            limit => limit.min(MAX_LIMIT),
+        };
+
+        let contents = self.repo.list_contents(req).await.map_err(to_status)?;
+
+        Ok(Response::new(stream::iter(contents.into_iter().map(Ok))))
+    }
+}
+
+fn required(field: &str, value: &str) -> Result<(), InvalidArgument> {
+    if value.trim().is_empty() {
+        return Err(InvalidArgument::new(format!("{field} is required")));
+    }
+    Ok(())
+}
+
+fn content_type(value: i32) -> Result<ContentType, InvalidArgument> {
+    ContentType::try_from(value).map_err(|_| InvalidArgument::new("Invalid content type"))
+}
+
+fn to_status(e: anyhow::Error) -> Status {
+    match e.downcast_ref::<UnknownPublishers>() {
+        Some(e) => Status::invalid_argument(e.to_string()),
+        None => Status::internal(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pb::PublisherIds, AppConfig, FakeRepo};
+    use crm_core::ConfigExt;
+    use futures::StreamExt;
+    use tonic::Code;
+
+    fn service() -> MetadataService<FakeRepo> {
+        let config = AppConfig::load().unwrap();
+        MetadataService::new(FakeRepo::new(), config)
+    }
+
+    async fn list(svc: &MetadataService<FakeRepo>, req: ListContentsRequest) -> Vec<Content> {
+        let ret = svc.list_contents(req).await.unwrap().into_inner();
+        ret.map(|c| c.unwrap()).collect().await
+    }
+
+    fn create_content_request(
+        name: &str,
+        r#type: ContentType,
+        publisher: u32,
+    ) -> CreateContentRequest {
+        CreateContentRequest {
+            name: name.to_string(),
+            description: format!("{name} description"),
+            publisher_ids: vec![publisher],
+            url: "https://placehold.co/1600x900".to_string(),
+            image: "https://placehold.co/1600x900".to_string(),
+            r#type: r#type as _,
+        }
+    }
+
+    #[tokio::test]
+    async fn contents_should_be_listed_and_searched() {
+        let svc = service();
+        let alice = svc
+            .create_publisher(CreatePublisherRequest {
+                name: "alice".to_string(),
+                avatar: "".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let bob = svc
+            .create_publisher(CreatePublisherRequest {
+                name: "bob".to_string(),
+                avatar: "".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        for req in [
+            create_content_request("Rust in action", ContentType::Vlog, alice.id),
+            create_content_request("Cooking pasta", ContentType::Short, alice.id),
+            create_content_request("Rust movie", ContentType::Movie, bob.id),
+        ] {
+            svc.create_content(req).await.unwrap();
+        }
+
+        let ret = list(
+            &svc,
+            ListContentsRequest {
+                publisher_id: Some(alice.id),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(ret.len(), 2);
+
+        let ret = list(
+            &svc,
+            ListContentsRequest {
+                r#type: Some(ContentType::Movie as _),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(ret.len(), 1);
+        assert_eq!(ret[0].publishers[0].name, "bob");
+
+        let ret = list(
+            &svc,
+            ListContentsRequest {
+                query: "rust".to_string(),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(ret.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn archived_content_should_not_be_listed() {
+        let svc = service();
+        let publisher = svc
+            .create_publisher(CreatePublisherRequest {
+                name: "alice".to_string(),
+                avatar: "".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let content = svc
+            .create_content(create_content_request(
+                "Rust in action",
+                ContentType::Vlog,
+                publisher.id,
+            ))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let content = svc
+            .update_content(UpdateContentRequest {
+                id: content.id,
+                name: Some("Rust in action 2".to_string()),
+                publishers: Some(PublisherIds { ids: vec![] }),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(content.name, "Rust in action 2");
+        assert!(content.publishers.is_empty());
+
+        let archived = svc
+            .archive_content(ArchiveContentRequest { id: content.id })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(archived.archived_at.is_some());
+        assert!(list(&svc, ListContentsRequest::default()).await.is_empty());
+
+        let ret = svc
+            .update_content(UpdateContentRequest {
+                id: content.id,
+                name: Some("Rust in action 3".to_string()),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(ret.unwrap_err().code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn content_with_unknown_publisher_should_be_rejected() {
+        let svc = service();
+        let ret = svc
+            .create_content(create_content_request("Rust", ContentType::Vlog, 42))
+            .await;
+        assert_eq!(ret.unwrap_err().code(), Code::InvalidArgument);
+
+        let ret = svc
+            .create_content(create_content_request("", ContentType::Vlog, 42))
+            .await;
+        assert_eq!(ret.unwrap_err().code(), Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Days, Utc};
+use fake::{
+    faker::{chrono::en::DateTimeBetween, lorem::en::Sentence, name::en::Name},
+    Fake, Faker,
+};
+use prost_types::Timestamp;
+use rand::Rng;
+use tracing::instrument;
+
+use super::{ContentRepo, UnknownPublishers};
+use crate::pb::{
+    Content, ContentType, CreateContentRequest, CreatePublisherRequest, ListContentsRequest,
+    Publisher, UpdateContentRequest, UpdatePublisherRequest,
+};
+
+/// An in-memory repository for tests only, the contents which are not created
+/// through the repository are fabricated with random data when materialized
+#[derive(Debug, Clone, Default)]
+pub struct FakeRepo {
+    catalog: Arc<RwLock<Catalog>>,
+}
+
+#[derive(Debug, Default)]
+struct Catalog {
+    contents: HashMap<u32, Content>,
+    publishers: HashMap<u32, Publisher>,
+    last_id: u32,
+}
+
+impl FakeRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ContentRepo for FakeRepo {
+    async fn materialize(&self, ids: &[u32]) -> Result<Vec<Content>> {
+        let catalog = self.catalog.read().map_err(|e| anyhow!("{e}"))?;
+        Ok(ids
+            .iter()
+            .filter_map(|id| match catalog.contents.get(id) {
+                Some(content) if content.archived_at.is_some() => None,
+                Some(content) => Some(content.clone()),
+                None => Some(Content::materialize(*id)),
+            })
+            .collect())
+    }
+
+    async fn create_content(&self, req: CreateContentRequest) -> Result<Content> {
+        let mut catalog = self.catalog.write().map_err(|e| anyhow!("{e}"))?;
+        let publishers = catalog.publishers(&req.publisher_ids)?;
+        let content = Content {
+            id: catalog.next_id(),
+            name: req.name,
+            description: req.description,
+            publishers,
+            url: req.url,
+            image: req.image,
+            r#type: req.r#type,
+            created_at: Some(now()),
+            ..Default::default()
+        };
+        catalog.contents.insert(content.id, content.clone());
+        Ok(content)
+    }
+
+    async fn update_content(&self, req: UpdateContentRequest) -> Result<Option<Content>> {
+        let mut catalog = self.catalog.write().map_err(|e| anyhow!("{e}"))?;
+        let publishers = match &req.publishers {
+            Some(publishers) => Some(catalog.publishers(&publishers.ids)?),
+            None => None,
+        };
+        let Some(content) = catalog
+            .contents
+            .get_mut(&req.id)
+            .filter(|c| c.archived_at.is_none())
+        else {
+            return Ok(None);
+        };
+
+        if let Some(name) = req.name {
+            content.name = name;
+        }
+        if let Some(description) = req.description {
+            content.description = description;
+        }
+        if let Some(publishers) = publishers {
+            content.publishers = publishers;
+        }
+        if let Some(url) = req.url {
+            content.url = url;
+        }
+        if let Some(image) = req.image {
+            content.image = image;
+        }
+        if let Some(r#type) = req.r#type {
+            content.r#type = r#type;
+        }
+        Ok(Some(content.clone()))
+    }
+
+    async fn archive_content(&self, id: u32) -> Result<Option<Content>> {
+        let mut catalog = self.catalog.write().map_err(|e| anyhow!("{e}"))?;
+        Ok(catalog.contents.get_mut(&id).map(|content| {
+            content.archived_at.get_or_insert_with(now);
+            content.clone()
+        }))
+    }
+
+    async fn create_publisher(&self, req: CreatePublisherRequest) -> Result<Publisher> {
+        let mut catalog = self.catalog.write().map_err(|e| anyhow!("{e}"))?;
+        let publisher = Publisher {
+            id: catalog.next_id(),
+            name: req.name,
+            avatar: req.avatar,
+            archived_at: None,
+        };
+        catalog.publishers.insert(publisher.id, publisher.clone());
+        Ok(publisher)
+    }
+
+    async fn update_publisher(&self, req: UpdatePublisherRequest) -> Result<Option<Publisher>> {
+        let mut catalog = self.catalog.write().map_err(|e| anyhow!("{e}"))?;
+        let Some(publisher) = catalog
+            .publishers
+            .get_mut(&req.id)
+            .filter(|p| p.archived_at.is_none())
+        else {
+            return Ok(None);
+        };
+
+        if let Some(name) = req.name {
+            publisher.name = name;
+        }
+        if let Some(avatar) = req.avatar {
+            publisher.avatar = avatar;
+        }
+        let publisher = publisher.clone();
+        catalog.sync_publisher(&publisher);
+        Ok(Some(publisher))
+    }
+
+    async fn archive_publisher(&self, id: u32) -> Result<Option<Publisher>> {
+        let mut catalog = self.catalog.write().map_err(|e| anyhow!("{e}"))?;
+        let Some(publisher) = catalog.publishers.get_mut(&id) else {
+            return Ok(None);
+        };
+        publisher.archived_at.get_or_insert_with(now);
+        let publisher = publisher.clone();
+        catalog.sync_publisher(&publisher);
+        Ok(Some(publisher))
+    }
+
+    async fn list_contents(&self, req: ListContentsRequest) -> Result<Vec<Content>> {
+        let catalog = self.catalog.read().map_err(|e| anyhow!("{e}"))?;
+        let query = req.query.to_lowercase();
+        let mut contents: Vec<_> = catalog
+            .contents
+            .values()
+            .filter(|c| c.archived_at.is_none())
+            .filter(|c| req.r#type.is_none_or(|t| c.r#type == t))
+            .filter(|c| {
+                req.publisher_id
+                    .is_none_or(|id| c.publishers.iter().any(|p| p.id == id))
+            })
+            .filter(|c| {
+                query.is_empty()
+                    || c.name.to_lowercase().contains(&query)
+                    || c.description.to_lowercase().contains(&query)
+            })
+            .cloned()
+            .collect();
+        contents.sort_by_key(|c| std::cmp::Reverse(c.id));
+
+        Ok(contents
+            .into_iter()
+            .skip(req.offset as _)
+            .take(req.limit as _)
+            .collect())
+    }
+
+    async fn candidates(
+        &self,
+        publisher_ids: &[u32],
+        types: &[ContentType],
+        exclude: &[u32],
+        limit: u32,
+    ) -> Result<Vec<Content>> {
+        let catalog = self.catalog.read().map_err(|e| anyhow!("{e}"))?;
+        let mut contents: Vec<_> = catalog
+            .contents
+            .values()
+            .filter(|c| c.archived_at.is_none() && !exclude.contains(&c.id))
+            .collect();
+        contents.sort_by_key(|c| std::cmp::Reverse(c.views));
+
+        let related = contents.iter().filter(|c| {
+            types.contains(&c.r#type())
+                || c.publishers.iter().any(|p| publisher_ids.contains(&p.id))
+        });
+        let candidates: HashMap<_, _> = contents
+            .iter()
+            .take(limit as _)
+            .chain(related.take(limit as _))
+            .map(|c| (c.id, (*c).clone()))
+            .collect();
+        Ok(candidates.into_values().collect())
+    }
+}
+
+impl Catalog {
+    fn next_id(&mut self) -> u32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// Publishers of the given ids, all of them must exist and not be archived
+    fn publishers(&self, ids: &[u32]) -> Result<Vec<Publisher>> {
+        let unknown: Vec<_> = ids
+            .iter()
+            .filter(|id| {
+                !self
+                    .publishers
+                    .get(id)
+                    .is_some_and(|p| p.archived_at.is_none())
+            })
+            .copied()
+            .collect();
+        if !unknown.is_empty() {
+            return Err(UnknownPublishers(unknown).into());
+        }
+        Ok(ids.iter().map(|id| self.publishers[id].clone()).collect())
+    }
+
+    /// Refresh the publisher embedded in the contents, archived ones are removed
+    fn sync_publisher(&mut self, publisher: &Publisher) {
+        for content in self.contents.values_mut() {
+            if publisher.archived_at.is_some() {
+                content.publishers.retain(|p| p.id != publisher.id);
+            } else if let Some(p) = content.publishers.iter_mut().find(|p| p.id == publisher.id) {
+                *p = publisher.clone();
+            }
+        }
+    }
+}
+
+impl Content {
+    #[instrument(name = "content-materialize", skip_all)]
+    pub fn materialize(id: u32) -> Self {
+        let mut rng = rand::thread_rng();
+        Content {
+            id,
+            name: Name().fake(),
+            description: Sentence(3..7).fake(),
+            publishers: (1..rng.gen_range(2..10))
+                .map(|_| Publisher::new())
+                .collect(),
+            url: "https://placehold.co/1600x900".to_string(),
+            image: "https://placehold.co/1600x900".to_string(),
+            r#type: Faker.fake(),
+            created_at: created_at(),
+            views: rng.gen_range(123432..10000000),
+            likes: rng.gen_range(1234..100000),
+            dislikes: rng.gen_range(123..10000),
+            archived_at: None,
+        }
+    }
+}
+
+impl Publisher {
+    pub fn new() -> Self {
+        Publisher {
+            id: (10000..2000000).fake(),
+            name: Name().fake(),
+            avatar: "https://placehold.co/400x400".to_string(),
+            archived_at: None,
+        }
+    }
+}
+
+fn before(days: u64) -> DateTime<Utc> {
+    Utc::now().checked_sub_days(Days::new(days)).unwrap()
+}
+
+fn now() -> Timestamp {
+    let now = Utc::now();
+    Timestamp {
+        seconds: now.timestamp(),
+        nanos: now.timestamp_subsec_nanos() as i32,
+    }
+}
+
+fn created_at() -> Option<Timestamp> {
+    let date: DateTime<Utc> = DateTimeBetween(before(365), before(0)).fake();
+    Some(Timestamp {
+        seconds: date.timestamp(),
+        nanos: date.timestamp_subsec_nanos() as i32,
+    })
+}
@@ -1,108 +1,240 @@
-use std::collections::HashSet;
+mod cache;
+mod catalog;
+#[cfg(feature = "test_utils")]
+mod fake_repo;
+mod postgres_repo;
+mod recommend;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
 use crate::{
-    pb::{Content, MaterializeRequest, Publisher},
+    metrics::record_contents_materialized,
+    pb::{
+        materialize_response, Content, ContentType, CreateContentRequest, CreatePublisherRequest,
+        ListContentsRequest, MaterializeError, MaterializeErrorKind, MaterializeRequest,
+        MaterializeResponse, Publisher, UpdateContentRequest, UpdatePublisherRequest,
+    },
     MetadataService, ServiceResult,
 };
-use chrono::{DateTime, Days, Utc};
-use fake::{
-    faker::{chrono::en::DateTimeBetween, lorem::en::Sentence, name::en::Name},
-    Fake, Faker,
-};
-use futures::{stream, Stream, StreamExt};
-use prost_types::Timestamp;
-use rand::Rng;
+use anyhow::Result;
+use futures::{future, stream, Stream, StreamExt};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Response, Status};
-use tracing::instrument;
+use tracing::{instrument, warn, Instrument};
+
+pub use cache::{CachedRepo, Lookup};
+#[cfg(feature = "test_utils")]
+pub use fake_repo::FakeRepo;
+pub use postgres_repo::PostgresRepo;
 
 const CHANNEL_SIZE: usize = 1024;
+/// Max number of ids materialized by one query
+const BATCH_SIZE: usize = 128;
+
+/// A repository of the content catalog
+pub trait ContentRepo: Send + Sync + 'static {
+    /// Materialize the contents with the given ids in one query,
+    /// unknown and archived ids are skipped
+    fn materialize(
+        &self,
+        ids: &[u32],
+    ) -> impl std::future::Future<Output = Result<Vec<Content>>> + Send;
+
+    /// Create a content, fails with [`UnknownPublishers`] if any publisher does not exist
+    fn create_content(
+        &self,
+        req: CreateContentRequest,
+    ) -> impl std::future::Future<Output = Result<Content>> + Send;
+
+    /// Update the fields which are set, none if the content does not exist or is archived
+    fn update_content(
+        &self,
+        req: UpdateContentRequest,
+    ) -> impl std::future::Future<Output = Result<Option<Content>>> + Send;
+
+    /// Archive a content, none if the content does not exist
+    fn archive_content(
+        &self,
+        id: u32,
+    ) -> impl std::future::Future<Output = Result<Option<Content>>> + Send;
+
+    fn create_publisher(
+        &self,
+        req: CreatePublisherRequest,
+    ) -> impl std::future::Future<Output = Result<Publisher>> + Send;
+
+    /// Update the fields which are set, none if the publisher does not exist or is archived
+    fn update_publisher(
+        &self,
+        req: UpdatePublisherRequest,
+    ) -> impl std::future::Future<Output = Result<Option<Publisher>>> + Send;
+
+    /// Archive a publisher, none if the publisher does not exist
+    fn archive_publisher(
+        &self,
+        id: u32,
+    ) -> impl std::future::Future<Output = Result<Option<Publisher>>> + Send;
+
+    /// List the contents which are not archived, the newest first
+    fn list_contents(
+        &self,
+        req: ListContentsRequest,
+    ) -> impl std::future::Future<Output = Result<Vec<Content>>> + Send;
 
-impl MetadataService {
+    /// Candidates to be recommended, which are not archived or excluded: at most `limit`
+    /// most viewed contents overall, and at most `limit` most viewed contents of either
+    /// the given publishers or types
+    fn candidates(
+        &self,
+        publisher_ids: &[u32],
+        types: &[ContentType],
+        exclude: &[u32],
+        limit: u32,
+    ) -> impl std::future::Future<Output = Result<Vec<Content>>> + Send;
+}
+
+/// Publishers referred by a content which do not exist or are archived
+#[derive(Debug)]
+pub struct UnknownPublishers(pub Vec<u32>);
+
+impl<R: ContentRepo> MetadataService<R> {
+    /// Materialize the requested contents in the order of the requests, the ids which
+    /// are already received are queried in one batch. Each request gets a response with
+    /// either the content or the reason it is not materialized.
     #[instrument(name = "materialize", skip_all)]
     pub async fn materialize(
         &self,
-        mut stream: impl Stream<Item = Result<MaterializeRequest, Status>> + Send + 'static + Unpin,
-    ) -> ServiceResult<impl Stream<Item = Result<Content, Status>> + Send> {
+        stream: impl Stream<Item = Result<MaterializeRequest, Status>> + Send + 'static + Unpin,
+    ) -> ServiceResult<impl Stream<Item = Result<MaterializeResponse, Status>> + Send> {
         let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
-        tokio::spawn(async move {
-            while let Some(Ok(req)) = stream.next().await {
-                let content = Content::materialize(req.id);
-                tx.send(Ok(content)).await.unwrap();
+        let svc = self.clone();
+        let task = async move {
+            let mut batches = stream
+                .take_while(|req| future::ready(req.is_ok()))
+                .filter_map(|req| future::ready(req.ok()))
+                .ready_chunks(BATCH_SIZE);
+
+            while let Some(reqs) = batches.next().await {
+                let ids: Vec<u32> = reqs.into_iter().map(|req| req.id).collect();
+                let responses: Vec<_> = match svc.repo.materialize(&ids).await {
+                    Ok(contents) => {
+                        let contents: HashMap<_, _> =
+                            contents.into_iter().map(|c| (c.id, c)).collect();
+                        record_contents_materialized(contents.len());
+                        ids.iter()
+                            .map(|id| match contents.get(id) {
+                                Some(content) => MaterializeResponse::found(content.clone()),
+                                None => MaterializeResponse::not_found(*id),
+                            })
+                            .collect()
+                    }
+                    Err(e) => {
+                        warn!("Failed to materialize contents: {:?}", e);
+                        ids.iter()
+                            .map(|id| MaterializeResponse::failed(*id, e.to_string()))
+                            .collect()
+                    }
+                };
+
+                for response in responses {
+                    if tx.send(Ok(response)).await.is_err() {
+                        // the client is gone
+                        return;
+                    }
+                }
             }
-        });
+        };
+        tokio::spawn(task.in_current_span());
 
         let stream = ReceiverStream::new(rx);
         Ok(Response::new(stream))
     }
 }
 
-impl Content {
-    #[instrument(name = "content-materialize", skip_all)]
-    pub fn materialize(id: u32) -> Self {
-        let mut rng = rand::thread_rng();
-        Content {
-            id,
-            name: Name().fake(),
-            description: Sentence(3..7).fake(),
-            publishers: (1..rng.gen_range(2..10))
-                .map(|_| Publisher::new())
-                .collect(),
-            url: "https://placehold.co/1600x900".to_string(),
-            image: "https://placehold.co/1600x900".to_string(),
-            r#type: Faker.fake(),
-            created_at: created_at(),
-            views: rng.gen_range(123432..10000000),
-            likes: rng.gen_range(1234..100000),
-            dislikes: rng.gen_range(123..10000),
-        }
+impl fmt::Display for UnknownPublishers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown publishers: {:?}", self.0)
     }
+}
 
+impl std::error::Error for UnknownPublishers {}
+
+impl Content {
     pub fn to_body(&self) -> String {
         format!("Content: {:?}", self)
     }
 }
 
 impl MaterializeRequest {
+    /// Requests of the given ids in order, the duplicated ids are requested once
     pub fn new_with_ids(ids: &[u32]) -> impl Stream<Item = Self> {
-        let reqs: HashSet<_> = ids.iter().map(|id| Self { id: *id }).collect();
+        let mut seen = HashSet::new();
+        let reqs: Vec<_> = ids
+            .iter()
+            .filter(|id| seen.insert(**id))
+            .map(|id| Self { id: *id })
+            .collect();
         stream::iter(reqs)
     }
 }
 
-impl Publisher {
-    pub fn new() -> Self {
-        Publisher {
-            id: (10000..2000000).fake(),
-            name: Name().fake(),
-            avatar: "https://placehold.co/400x400".to_string(),
+impl MaterializeResponse {
+    pub fn found(content: Content) -> Self {
+        Self {
+            id: content.id,
+            result: Some(materialize_response::Result::Content(content)),
         }
     }
-}
 
-fn before(days: u64) -> DateTime<Utc> {
-    Utc::now().checked_sub_days(Days::new(days)).unwrap()
-}
+    pub fn not_found(id: u32) -> Self {
+        Self::error(
+            id,
+            MaterializeErrorKind::NotFound,
+            format!("Content {id} not found"),
+        )
+    }
 
-fn created_at() -> Option<Timestamp> {
-    let date: DateTime<Utc> = DateTimeBetween(before(365), before(0)).fake();
-    Some(Timestamp {
-        seconds: date.timestamp(),
-        nanos: date.timestamp_subsec_nanos() as i32,
-    })
+    pub fn failed(id: u32, message: String) -> Self {
+        Self::error(id, MaterializeErrorKind::Internal, message)
+    }
+
+    fn error(id: u32, kind: MaterializeErrorKind, message: String) -> Self {
+        Self {
+            id,
+            result: Some(materialize_response::Result::Error(MaterializeError {
+                kind: kind as _,
+                message,
+            })),
+        }
+    }
+
+    /// The content, or the reason it is not materialized
+    pub fn into_result(self) -> Result<Content, MaterializeError> {
+        match self.result {
+            Some(materialize_response::Result::Content(content)) => Ok(content),
+            Some(materialize_response::Result::Error(e)) => Err(e),
+            None => Err(MaterializeError {
+                kind: MaterializeErrorKind::Unspecified as _,
+                message: format!("Content {} has no result", self.id),
+            }),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::AppConfig;
+    use crate::{AppConfig, FakeRepo};
     use crm_core::ConfigExt;
 
     #[tokio::test]
     async fn materialize_should_work() {
         let config = AppConfig::load().unwrap();
-        let service = MetadataService::new(config);
+        let service = MetadataService::new(FakeRepo::new(), config);
         let stream = tokio_stream::iter(vec![
             Ok(MaterializeRequest { id: 1 }),
             Ok(MaterializeRequest { id: 2 }),
@@ -113,4 +245,36 @@ mod tests {
         let ret = response.into_inner().collect::<Vec<_>>().await;
         assert_eq!(ret.len(), 3);
     }
+
+    #[tokio::test]
+    async fn materialize_should_keep_order_and_report_not_found() {
+        let config = AppConfig::load().unwrap();
+        let service = MetadataService::new(FakeRepo::new(), config);
+        let content = service
+            .create_content(CreateContentRequest {
+                name: "Rust".to_string(),
+                url: "https://placehold.co/1600x900".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        service
+            .archive_content(crate::pb::ArchiveContentRequest { id: content.id })
+            .await
+            .unwrap();
+
+        let reqs = MaterializeRequest::new_with_ids(&[3, content.id, 2, 3]).map(Ok);
+        let response = service.materialize(Box::pin(reqs)).await.unwrap();
+        let ret: Vec<_> = response
+            .into_inner()
+            .map(|r| r.unwrap().into_result().map_err(|e| e.kind()))
+            .collect()
+            .await;
+
+        assert_eq!(ret.len(), 3);
+        assert_eq!(ret[0].as_ref().unwrap().id, 3);
+        assert_eq!(ret[1], Err(MaterializeErrorKind::NotFound));
+        assert_eq!(ret[2].as_ref().unwrap().id, 2);
+    }
 }
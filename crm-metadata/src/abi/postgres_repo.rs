@@ -0,0 +1,407 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use crm_core::health::Ping;
+use prost_types::Timestamp;
+use sqlx::{PgConnection, PgPool};
+use tracing::instrument;
+
+use super::{ContentRepo, UnknownPublishers};
+use crate::pb::{
+    Content, ContentType, CreateContentRequest, CreatePublisherRequest, ListContentsRequest,
+    Publisher, UpdateContentRequest, UpdatePublisherRequest,
+};
+
+/// Contents with their publishers which are not archived, the publishers are aggregated
+/// into arrays so that the contents are loaded by one query. To be followed by the
+/// `WHERE` and `GROUP BY c.id` clauses.
+const SELECT_CONTENTS: &str = "SELECT c.id, c.name, c.description, c.url, c.image,
+    c.type::text AS content_type, c.created_at, c.views, c.likes, c.dislikes, c.archived_at,
+    COALESCE(array_agg(p.id ORDER BY p.id) FILTER (WHERE p.id IS NOT NULL), '{}') AS publisher_ids,
+    COALESCE(array_agg(p.name ORDER BY p.id) FILTER (WHERE p.id IS NOT NULL), '{}') AS publisher_names,
+    COALESCE(array_agg(p.avatar ORDER BY p.id) FILTER (WHERE p.id IS NOT NULL), '{}') AS publisher_avatars
+FROM contents c
+LEFT JOIN content_publishers cp ON cp.content_id = c.id
+LEFT JOIN publishers p ON p.id = cp.publisher_id AND p.archived_at IS NULL";
+
+/// A repository backed by postgres, see `migrations` for the schema
+#[derive(Clone)]
+pub struct PostgresRepo {
+    pool: PgPool,
+}
+
+#[derive(sqlx::FromRow)]
+struct ContentRow {
+    id: i32,
+    name: String,
+    description: String,
+    url: String,
+    image: String,
+    content_type: String,
+    created_at: DateTime<Utc>,
+    views: i64,
+    likes: i64,
+    dislikes: i64,
+    archived_at: Option<DateTime<Utc>>,
+    publisher_ids: Vec<i32>,
+    publisher_names: Vec<String>,
+    publisher_avatars: Vec<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct PublisherRow {
+    id: i32,
+    name: String,
+    avatar: String,
+    archived_at: Option<DateTime<Utc>>,
+}
+
+impl PostgresRepo {
+    pub async fn new(url: &str) -> Result<Self> {
+        let pool = PgPool::connect(url).await?;
+        Ok(Self { pool })
+    }
+}
+
+impl Ping for PostgresRepo {
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+impl ContentRepo for PostgresRepo {
+    #[instrument(name = "materialize-postgres", skip_all)]
+    async fn materialize(&self, ids: &[u32]) -> Result<Vec<Content>> {
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let ids: Vec<i32> = ids.iter().map(|id| *id as i32).collect();
+        let rows = sqlx::query_as::<_, ContentRow>(&format!(
+            "{SELECT_CONTENTS} WHERE c.id = ANY($1) AND c.archived_at IS NULL GROUP BY c.id"
+        ))
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| row.into()).collect())
+    }
+
+    #[instrument(name = "create-content-postgres", skip_all)]
+    async fn create_content(&self, req: CreateContentRequest) -> Result<Content> {
+        let mut tx = self.pool.begin().await?;
+        let id = sqlx::query_scalar::<_, i32>(
+            "INSERT INTO contents(name, description, url, image, type)
+            VALUES ($1, $2, $3, $4, $5::content_type)
+            RETURNING id",
+        )
+        .bind(&req.name)
+        .bind(&req.description)
+        .bind(&req.url)
+        .bind(&req.image)
+        .bind(content_type_name(req.r#type()))
+        .fetch_one(&mut *tx)
+        .await?;
+        set_publishers(&mut tx, id, &req.publisher_ids).await?;
+        tx.commit().await?;
+
+        let content = self.fetch_content(id).await?;
+        content.ok_or_else(|| anyhow::anyhow!("Content {id} is deleted after created"))
+    }
+
+    #[instrument(name = "update-content-postgres", skip_all)]
+    async fn update_content(&self, req: UpdateContentRequest) -> Result<Option<Content>> {
+        let mut tx = self.pool.begin().await?;
+        let id = sqlx::query_scalar::<_, i32>(
+            "UPDATE contents SET name = COALESCE($2, name),
+                description = COALESCE($3, description),
+                url = COALESCE($4, url),
+                image = COALESCE($5, image),
+                type = COALESCE($6::content_type, type)
+            WHERE id = $1 AND archived_at IS NULL
+            RETURNING id",
+        )
+        .bind(req.id as i32)
+        .bind(&req.name)
+        .bind(&req.description)
+        .bind(&req.url)
+        .bind(&req.image)
+        .bind(req.r#type.map(|_| content_type_name(req.r#type())))
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        if let Some(publishers) = &req.publishers {
+            sqlx::query("DELETE FROM content_publishers WHERE content_id = $1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            set_publishers(&mut tx, id, &publishers.ids).await?;
+        }
+        tx.commit().await?;
+
+        self.fetch_content(id).await
+    }
+
+    #[instrument(name = "archive-content-postgres", skip_all)]
+    async fn archive_content(&self, id: u32) -> Result<Option<Content>> {
+        let id = sqlx::query_scalar::<_, i32>(
+            "UPDATE contents SET archived_at = COALESCE(archived_at, now())
+            WHERE id = $1
+            RETURNING id",
+        )
+        .bind(id as i32)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match id {
+            Some(id) => self.fetch_content(id).await,
+            None => Ok(None),
+        }
+    }
+
+    #[instrument(name = "create-publisher-postgres", skip_all)]
+    async fn create_publisher(&self, req: CreatePublisherRequest) -> Result<Publisher> {
+        let row = sqlx::query_as::<_, PublisherRow>(
+            "INSERT INTO publishers(name, avatar) VALUES ($1, $2)
+            RETURNING id, name, avatar, archived_at",
+        )
+        .bind(&req.name)
+        .bind(&req.avatar)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.into())
+    }
+
+    #[instrument(name = "update-publisher-postgres", skip_all)]
+    async fn update_publisher(&self, req: UpdatePublisherRequest) -> Result<Option<Publisher>> {
+        let row = sqlx::query_as::<_, PublisherRow>(
+            "UPDATE publishers SET name = COALESCE($2, name), avatar = COALESCE($3, avatar)
+            WHERE id = $1 AND archived_at IS NULL
+            RETURNING id, name, avatar, archived_at",
+        )
+        .bind(req.id as i32)
+        .bind(&req.name)
+        .bind(&req.avatar)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| row.into()))
+    }
+
+    #[instrument(name = "archive-publisher-postgres", skip_all)]
+    async fn archive_publisher(&self, id: u32) -> Result<Option<Publisher>> {
+        let row = sqlx::query_as::<_, PublisherRow>(
+            "UPDATE publishers SET archived_at = COALESCE(archived_at, now())
+            WHERE id = $1
+            RETURNING id, name, avatar, archived_at",
+        )
+        .bind(id as i32)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| row.into()))
+    }
+
+    #[instrument(name = "list-contents-postgres", skip_all)]
+    async fn list_contents(&self, req: ListContentsRequest) -> Result<Vec<Content>> {
+        // unset filters bind NULL or an empty query and match all contents
+        let rows = sqlx::query_as::<_, ContentRow>(&format!(
+            "{SELECT_CONTENTS}
+            WHERE c.archived_at IS NULL
+                AND ($1::text IS NULL OR c.type = $1::content_type)
+                AND ($2::int IS NULL OR c.id IN (
+                    SELECT content_id FROM content_publishers WHERE publisher_id = $2))
+                AND ($3 = '' OR to_tsvector('english', c.name || ' ' || c.description)
+                    @@ plainto_tsquery('english', $3))
+            GROUP BY c.id
+            ORDER BY c.created_at DESC, c.id DESC
+            LIMIT $4 OFFSET $5"
+        ))
+        .bind(req.r#type.map(|_| content_type_name(req.r#type())))
+        .bind(req.publisher_id.map(|id| id as i32))
+        .bind(&req.query)
+        .bind(req.limit as i64)
+        .bind(req.offset as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| row.into()).collect())
+    }
+
+    #[instrument(name = "candidates-postgres", skip_all)]
+    async fn candidates(
+        &self,
+        publisher_ids: &[u32],
+        types: &[ContentType],
+        exclude: &[u32],
+        limit: u32,
+    ) -> Result<Vec<Content>> {
+        let publisher_ids: Vec<i32> = publisher_ids.iter().map(|id| *id as i32).collect();
+        let types: Vec<String> = types.iter().map(|t| content_type_name(*t)).collect();
+        let exclude: Vec<i32> = exclude.iter().map(|id| *id as i32).collect();
+        let rows = sqlx::query_as::<_, ContentRow>(&format!(
+            "{SELECT_CONTENTS}
+            WHERE c.id IN (
+                (SELECT id FROM contents
+                WHERE archived_at IS NULL AND NOT id = ANY($3)
+                ORDER BY views DESC LIMIT $4)
+                UNION
+                (SELECT id FROM contents
+                WHERE archived_at IS NULL AND NOT id = ANY($3)
+                    AND (type::text = ANY($2) OR id IN (
+                        SELECT content_id FROM content_publishers WHERE publisher_id = ANY($1)))
+                ORDER BY views DESC LIMIT $4))
+            GROUP BY c.id"
+        ))
+        .bind(publisher_ids)
+        .bind(types)
+        .bind(exclude)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| row.into()).collect())
+    }
+}
+
+impl PostgresRepo {
+    /// Load a content whether it is archived or not
+    async fn fetch_content(&self, id: i32) -> Result<Option<Content>> {
+        let row = sqlx::query_as::<_, ContentRow>(&format!(
+            "{SELECT_CONTENTS} WHERE c.id = $1 GROUP BY c.id"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| row.into()))
+    }
+}
+
+/// Link the publishers to a content, all of them must exist and not be archived
+async fn set_publishers(conn: &mut PgConnection, content_id: i32, ids: &[u32]) -> Result<()> {
+    let ids: Vec<i32> = ids.iter().map(|id| *id as i32).collect();
+    let found = sqlx::query_scalar::<_, i32>(
+        "SELECT id FROM publishers WHERE id = ANY($1) AND archived_at IS NULL FOR SHARE",
+    )
+    .bind(&ids)
+    .fetch_all(&mut *conn)
+    .await?;
+    let unknown: Vec<u32> = ids
+        .iter()
+        .filter(|id| !found.contains(id))
+        .map(|id| *id as u32)
+        .collect();
+    if !unknown.is_empty() {
+        return Err(UnknownPublishers(unknown).into());
+    }
+
+    sqlx::query(
+        "INSERT INTO content_publishers(content_id, publisher_id)
+        SELECT $1, unnest($2::int[])
+        ON CONFLICT DO NOTHING",
+    )
+    .bind(content_id)
+    .bind(&ids)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+impl From<ContentRow> for Content {
+    fn from(row: ContentRow) -> Self {
+        let content_type = ContentType::from_str_name(&format!(
+            "CONTENT_TYPE_{}",
+            row.content_type.to_uppercase()
+        ))
+        .unwrap_or_default();
+        let publishers = row
+            .publisher_ids
+            .into_iter()
+            .zip(row.publisher_names)
+            .zip(row.publisher_avatars)
+            .map(|((id, name), avatar)| Publisher {
+                id: id as _,
+                name,
+                avatar,
+                archived_at: None,
+            })
+            .collect();
+
+        Content {
+            id: row.id as _,
+            name: row.name,
+            description: row.description,
+            publishers,
+            url: row.url,
+            image: row.image,
+            r#type: content_type as _,
+            created_at: Some(to_ts(row.created_at)),
+            views: row.views as _,
+            likes: row.likes as _,
+            dislikes: row.dislikes as _,
+            archived_at: row.archived_at.map(to_ts),
+        }
+    }
+}
+
+impl From<PublisherRow> for Publisher {
+    fn from(row: PublisherRow) -> Self {
+        Publisher {
+            id: row.id as _,
+            name: row.name,
+            avatar: row.avatar,
+            archived_at: row.archived_at.map(to_ts),
+        }
+    }
+}
+
+/// Name of the `content_type` postgres enum value, e.g. `ai_generated`
+fn content_type_name(content_type: ContentType) -> String {
+    content_type
+        .as_str_name()
+        .trim_start_matches("CONTENT_TYPE_")
+        .to_lowercase()
+}
+
+fn to_ts(date: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: date.timestamp(),
+        nanos: date.timestamp_subsec_nanos() as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_row_should_convert_to_content() {
+        let row = ContentRow {
+            id: 1,
+            name: "Trailer".to_string(),
+            description: "A trailer".to_string(),
+            url: "https://placehold.co/1600x900".to_string(),
+            image: "https://placehold.co/1600x900".to_string(),
+            content_type: "ai_generated".to_string(),
+            created_at: Utc::now(),
+            views: 100,
+            likes: 10,
+            dislikes: 1,
+            archived_at: None,
+            publisher_ids: vec![1, 2],
+            publisher_names: vec!["alice".to_string(), "bob".to_string()],
+            publisher_avatars: vec!["a".to_string(), "b".to_string()],
+        };
+
+        let content: Content = row.into();
+        assert_eq!(content.r#type(), ContentType::AiGenerated);
+        assert_eq!(content.publishers.len(), 2);
+        assert_eq!(content.publishers[1].name, "bob");
+        assert_eq!(content.views, 100);
+        assert_eq!(content_type_name(ContentType::AiGenerated), "ai_generated");
+    }
+}
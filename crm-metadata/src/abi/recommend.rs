@@ -0,0 +1,204 @@
+use std::collections::HashSet;
+
+use super::ContentRepo;
+use crate::{
+    pb::{Content, ContentType, RecommendRequest},
+    MetadataService, ServiceResult,
+};
+use futures::{stream, Stream};
+use tonic::{Response, Status};
+use tracing::{debug, instrument};
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 100;
+/// Number of candidates of each kind loaded per recommended content
+const CANDIDATE_FACTOR: u32 = 5;
+/// Boost of a content sharing a publisher with the watched ones
+const PUBLISHER_AFFINITY: f64 = 1.0;
+/// Boost of a content of the same type as the watched ones
+const TYPE_AFFINITY: f64 = 0.5;
+
+impl<R: ContentRepo> MetadataService<R> {
+    /// Recommend the contents a user may like, ranked by their popularity and
+    /// their affinity with the contents the user watched
+    #[instrument(name = "recommend", skip_all)]
+    pub async fn recommend(
+        &self,
+        req: RecommendRequest,
+    ) -> ServiceResult<impl Stream<Item = Result<Content, Status>> + Send + 'static> {
+        let limit = match req.limit {
+            0 => DEFAULT_LIMIT,
+            limit => limit.min(MAX_LIMIT),
+        };
+
+        let watched: Vec<u32> = req
+            .recent_watched
+            .iter()
+            .chain(req.finished.iter())
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let watched = self
+            .repo
+            .materialize(&watched)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let affinity = Affinity::new(&watched);
+
+        let candidates = self
+            .repo
+            .candidates(
+                &affinity.publishers.iter().copied().collect::<Vec<_>>(),
+                &affinity.types.iter().copied().collect::<Vec<_>>(),
+                &req.finished,
+                limit * CANDIDATE_FACTOR,
+            )
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        debug!("ranking {} candidates", candidates.len());
+
+        let contents = affinity.rank(candidates, &req.finished, limit as _);
+        Ok(Response::new(stream::iter(contents.into_iter().map(Ok))))
+    }
+}
+
+/// Publishers and types of the contents a user watched
+struct Affinity {
+    publishers: HashSet<u32>,
+    types: HashSet<ContentType>,
+}
+
+impl Affinity {
+    fn new(watched: &[Content]) -> Self {
+        Self {
+            publishers: watched
+                .iter()
+                .flat_map(|c| c.publishers.iter().map(|p| p.id))
+                .collect(),
+            types: watched
+                .iter()
+                .map(|c| c.r#type())
+                .filter(|t| *t != ContentType::Unspecified)
+                .collect(),
+        }
+    }
+
+    /// The top `limit` contents by score, the excluded ones are dropped
+    fn rank(&self, contents: Vec<Content>, exclude: &[u32], limit: usize) -> Vec<Content> {
+        let mut scored: Vec<_> = contents
+            .into_iter()
+            .filter(|c| !exclude.contains(&c.id))
+            .map(|c| (self.score(&c), c))
+            .collect();
+        scored.sort_by(|(a, c1), (b, c2)| b.total_cmp(a).then(c1.id.cmp(&c2.id)));
+        scored.into_iter().take(limit).map(|(_, c)| c).collect()
+    }
+
+    fn score(&self, content: &Content) -> f64 {
+        let mut score = popularity(content) + 1.0;
+        if content
+            .publishers
+            .iter()
+            .any(|p| self.publishers.contains(&p.id))
+        {
+            score *= 1.0 + PUBLISHER_AFFINITY;
+        }
+        if self.types.contains(&content.r#type()) {
+            score *= 1.0 + TYPE_AFFINITY;
+        }
+        score
+    }
+}
+
+/// Popularity of a content: the views in log scale, weighted by the ratio of likes
+fn popularity(content: &Content) -> f64 {
+    let ratio = (content.likes as f64 + 1.0) / ((content.likes + content.dislikes) as f64 + 2.0);
+    (content.views as f64).ln_1p() * ratio
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pb::{CreateContentRequest, CreatePublisherRequest, Publisher},
+        AppConfig, FakeRepo,
+    };
+    use crm_core::ConfigExt;
+    use futures::StreamExt;
+
+    fn content(id: u32, r#type: ContentType, publisher: u32, views: u64, likes: u64) -> Content {
+        Content {
+            id,
+            r#type: r#type as _,
+            publishers: vec![Publisher {
+                id: publisher,
+                ..Default::default()
+            }],
+            views,
+            likes,
+            dislikes: 10,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn contents_should_be_ranked_by_popularity_and_affinity() {
+        let affinity = Affinity::new(&[content(1, ContentType::Vlog, 1, 0, 0)]);
+        let contents = vec![
+            content(2, ContentType::Movie, 2, 100_000, 1000),
+            content(3, ContentType::Movie, 2, 100_000, 10),
+            content(4, ContentType::Vlog, 1, 1000, 100),
+            content(5, ContentType::Vlog, 1, 1000, 100),
+        ];
+
+        let ret = affinity.rank(contents, &[5], 3);
+        let ids: Vec<_> = ret.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![4, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn recommend_should_exclude_finished_contents() {
+        let config = AppConfig::load().unwrap();
+        let svc = MetadataService::new(FakeRepo::new(), config);
+        let publisher = svc
+            .create_publisher(CreatePublisherRequest {
+                name: "alice".to_string(),
+                avatar: "".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let mut ids = vec![];
+        for (name, r#type, publisher_ids) in [
+            ("Rust in action", ContentType::Vlog, vec![publisher.id]),
+            ("Rust in action 2", ContentType::Vlog, vec![publisher.id]),
+            ("Cooking pasta", ContentType::Short, vec![]),
+        ] {
+            let content = svc
+                .create_content(CreateContentRequest {
+                    name: name.to_string(),
+                    publisher_ids,
+                    url: "https://placehold.co/1600x900".to_string(),
+                    r#type: r#type as _,
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            ids.push(content.id);
+        }
+
+        let ret = svc
+            .recommend(RecommendRequest {
+                recent_watched: vec![],
+                finished: vec![ids[0]],
+                limit: 0,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let ret: Vec<_> = ret.map(|c| c.unwrap().id).collect().await;
+        assert_eq!(ret, vec![ids[1], ids[2]]);
+    }
+}
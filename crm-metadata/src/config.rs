@@ -1,4 +1,4 @@
-use crm_core::telemetry;
+use crm_core::{auth::AuthConfig, telemetry, TlsConfig};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -9,11 +9,29 @@ pub struct AppConfig {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct AuthConfig {
-    pub pk: String,
+pub struct ServerConfig {
+    pub port: u16,
+    pub db_url: String,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// serve over TLS if set
+    pub tls: Option<TlsConfig>,
 }
 
+/// Cache of the materialized contents
 #[derive(Debug, Serialize, Deserialize)]
-pub struct ServerConfig {
-    pub port: u16,
+pub struct CacheConfig {
+    /// max number of cached contents
+    pub capacity: usize,
+    /// seconds a content is cached for
+    pub ttl: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 10000,
+            ttl: 300,
+        }
+    }
 }
@@ -4,26 +4,42 @@ pub mod pb;
 
 mod abi;
 mod config;
+mod metrics;
 
-pub use config::AppConfig;
+use std::{ops::Deref, sync::Arc};
+
+#[cfg(feature = "test_utils")]
+pub use abi::FakeRepo;
+pub use abi::{CachedRepo, ContentRepo, Lookup, PostgresRepo, UnknownPublishers};
+pub use config::{AppConfig, CacheConfig};
 use futures::Stream;
 use pb::{
     metadata_server::{Metadata, MetadataServer},
-    Content, MaterializeRequest,
+    ArchiveContentRequest, ArchivePublisherRequest, Content, CreateContentRequest,
+    CreatePublisherRequest, ListContentsRequest, MaterializeRequest, MaterializeResponse,
+    Publisher, RecommendRequest, UpdateContentRequest, UpdatePublisherRequest,
 };
 use tonic::{async_trait, Request, Response, Status, Streaming};
 use tracing::instrument;
 
+pub struct MetadataService<R> {
+    inner: Arc<MetadataServiceInner<R>>,
+}
+
 #[allow(unused)]
-pub struct MetadataService {
+pub struct MetadataServiceInner<R> {
     config: AppConfig,
+    /// Storage of the content catalog
+    repo: R,
 }
 
 type ServiceResult<T> = Result<Response<T>, Status>;
 
 #[async_trait]
-impl Metadata for MetadataService {
-    type MaterializeStream = impl Stream<Item = Result<Content, Status>> + Send;
+impl<R: ContentRepo> Metadata for MetadataService<R> {
+    type MaterializeStream = impl Stream<Item = Result<MaterializeResponse, Status>> + Send;
+    type ListContentsStream = impl Stream<Item = Result<Content, Status>> + Send;
+    type RecommendStream = impl Stream<Item = Result<Content, Status>> + Send;
 
     #[instrument(name = "materialize-handler", skip_all)]
     async fn materialize(
@@ -33,14 +49,97 @@ impl Metadata for MetadataService {
         let query = request.into_inner();
         self.materialize(query).await
     }
+
+    #[instrument(name = "create-content-handler", skip_all)]
+    async fn create_content(
+        &self,
+        request: Request<CreateContentRequest>,
+    ) -> ServiceResult<Content> {
+        self.create_content(request.into_inner()).await
+    }
+
+    #[instrument(name = "update-content-handler", skip_all)]
+    async fn update_content(
+        &self,
+        request: Request<UpdateContentRequest>,
+    ) -> ServiceResult<Content> {
+        self.update_content(request.into_inner()).await
+    }
+
+    #[instrument(name = "archive-content-handler", skip_all)]
+    async fn archive_content(
+        &self,
+        request: Request<ArchiveContentRequest>,
+    ) -> ServiceResult<Content> {
+        self.archive_content(request.into_inner()).await
+    }
+
+    #[instrument(name = "create-publisher-handler", skip_all)]
+    async fn create_publisher(
+        &self,
+        request: Request<CreatePublisherRequest>,
+    ) -> ServiceResult<Publisher> {
+        self.create_publisher(request.into_inner()).await
+    }
+
+    #[instrument(name = "update-publisher-handler", skip_all)]
+    async fn update_publisher(
+        &self,
+        request: Request<UpdatePublisherRequest>,
+    ) -> ServiceResult<Publisher> {
+        self.update_publisher(request.into_inner()).await
+    }
+
+    #[instrument(name = "archive-publisher-handler", skip_all)]
+    async fn archive_publisher(
+        &self,
+        request: Request<ArchivePublisherRequest>,
+    ) -> ServiceResult<Publisher> {
+        self.archive_publisher(request.into_inner()).await
+    }
+
+    #[instrument(name = "list-contents-handler", skip_all)]
+    async fn list_contents(
+        &self,
+        request: Request<ListContentsRequest>,
+    ) -> ServiceResult<Self::ListContentsStream> {
+        self.list_contents(request.into_inner()).await
+    }
+
+    #[instrument(name = "recommend-handler", skip_all)]
+    async fn recommend(
+        &self,
+        request: Request<RecommendRequest>,
+    ) -> ServiceResult<Self::RecommendStream> {
+        self.recommend(request.into_inner()).await
+    }
 }
 
-impl MetadataService {
-    pub fn new(config: AppConfig) -> Self {
-        MetadataService { config }
+impl<R: ContentRepo> MetadataService<R> {
+    pub fn new(repo: R, config: AppConfig) -> Self {
+        let inner = MetadataServiceInner { config, repo };
+        MetadataService {
+            inner: Arc::new(inner),
+        }
     }
 
     pub fn into_server(self) -> MetadataServer<Self> {
         MetadataServer::new(self)
     }
 }
+
+impl<R> Clone for MetadataService<R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<R> Deref for MetadataService<R> {
+    type Target = MetadataServiceInner<R>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
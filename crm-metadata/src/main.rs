@@ -1,8 +1,14 @@
 use std::mem;
 
 use anyhow::Result;
-use crm_core::{accept_trace, log_error, make_span, telemetry, ConfigExt};
-use crm_metadata::{AppConfig, MetadataService};
+use crm_core::{
+    accept_trace, auth::AuthLayer, health::report_ping, log_error, make_span,
+    metrics::GrpcMetricsLayer, telemetry, ConfigExt,
+};
+use crm_metadata::{
+    pb::{metadata_server::SERVICE_NAME, FILE_DESCRIPTOR_SET},
+    AppConfig, CachedRepo, MetadataService, PostgresRepo,
+};
 use tonic::transport::Server;
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
@@ -20,11 +26,34 @@ async fn main() -> Result<()> {
     let addr = config.server.port;
     let addr = format!("127.0.0.1:{}", addr).parse().unwrap();
     info!("Metadata service listening on {}", addr);
-    let svc = MetadataService::new(config).into_server();
+    let dk = config.auth.decoding_key()?;
+    let authz = config.auth.authz_layer();
+    let repo = PostgresRepo::new(&config.server.db_url).await?;
 
-    Server::builder()
+    // ready while the database is reachable
+    let (reporter, health) = tonic_health::server::health_reporter();
+    report_ping(reporter, vec![SERVICE_NAME, ""], repo.clone());
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .build_v1()?;
+
+    let repo = CachedRepo::new(repo, &config.server.cache);
+    let server = match &config.server.tls {
+        Some(tls) => Server::builder().tls_config(tls.server_config()?)?,
+        None => {
+            info!("TLS is not enabled");
+            Server::builder()
+        }
+    };
+    let svc = MetadataService::new(repo, config).into_server();
+
+    server
         .layer(
             ServiceBuilder::new()
+                .layer(GrpcMetricsLayer::new(&[
+                    FILE_DESCRIPTOR_SET,
+                    tonic_reflection::pb::v1::FILE_DESCRIPTOR_SET,
+                ])?)
                 .layer(tower::timeout::TimeoutLayer::new(
                     std::time::Duration::from_secs(30),
                 ))
@@ -32,8 +61,12 @@ async fn main() -> Result<()> {
                     TraceLayer::new_for_grpc()
                         .make_span_with(make_span)
                         .on_request(accept_trace),
-                ),
+                )
+                .layer(AuthLayer::new(dk))
+                .layer(authz),
         )
+        .add_service(health)
+        .add_service(reflection)
         .add_service(svc)
         .serve(addr)
         .await?;
@@ -0,0 +1,30 @@
+use std::sync::LazyLock;
+
+use prometheus::{register_int_counter, register_int_counter_vec, IntCounter, IntCounterVec};
+
+static CONTENTS_MATERIALIZED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "metadata_contents_materialized_total",
+        "Contents found and returned by the materialize calls"
+    )
+    .expect("metric is registered once")
+});
+
+static CACHE_LOOKUPS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "metadata_content_cache_lookups_total",
+        "Content cache lookups by result, hit or miss",
+        &["result"]
+    )
+    .expect("metric is registered once")
+});
+
+pub(crate) fn record_contents_materialized(count: usize) {
+    CONTENTS_MATERIALIZED.inc_by(count as u64);
+}
+
+/// Record a lookup of the content cache, an id cached as not found is a hit
+pub(crate) fn record_cache_lookup(hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    CACHE_LOOKUPS.with_label_values(&[result]).inc();
+}
@@ -1,3 +1,6 @@
 mod metadata;
 
 pub use metadata::*;
+
+/// Encoded descriptors of the protos, served by the reflection service
+pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!("metadata_descriptor.bin");
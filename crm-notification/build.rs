@@ -4,13 +4,16 @@ use std::fs;
 fn main() -> Result<()> {
     fs::create_dir_all("src/pb")?;
     let builder = tonic_build::configure();
-    builder.out_dir("src/pb").compile(
-        &[
-            "../protos/notification/message.proto",
-            "../protos/notification/rpc.proto",
-        ],
-        &["../protos/notification"],
-    )?;
+    builder
+        .out_dir("src/pb")
+        .file_descriptor_set_path("src/pb/notification_descriptor.bin")
+        .compile(
+            &[
+                "../protos/notification/message.proto",
+                "../protos/notification/rpc.proto",
+            ],
+            &["../protos/notification"],
+        )?;
 
     Ok(())
 }
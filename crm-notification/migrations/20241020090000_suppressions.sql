@@ -0,0 +1,22 @@
+-- Add migration script here
+CREATE TYPE channel AS ENUM(
+  'email',
+  'sms',
+  'in_app'
+);
+
+CREATE TYPE suppression_reason AS ENUM(
+  'unspecified',
+  'unsubscribed',
+  'hard_bounce',
+  'complaint',
+  'manual'
+);
+
+CREATE TABLE suppressions(
+  channel channel NOT NULL,
+  recipient varchar(256) NOT NULL,
+  reason suppression_reason NOT NULL DEFAULT 'unspecified',
+  created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (channel, recipient)
+);
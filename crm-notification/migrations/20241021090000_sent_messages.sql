@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE sent_messages(
+  message_id varchar(64) NOT NULL PRIMARY KEY,
+  -- protobuf encoded SendResponse, NULL while the message is being sent
+  response bytea,
+  expires_at timestamptz NOT NULL
+);
+
+CREATE INDEX sent_messages_expires_at_idx ON sent_messages(expires_at);
@@ -1,24 +1,29 @@
-use super::{to_ts, Sender};
+use super::{filter_suppressed, send_response, Delivery, Repo, Sender};
 use crate::{
-    pb::{send_request::Msg, InAppMessage, SendRequest, SendResponse},
+    pb::{send_request::Msg, Channel, InAppMessage, SendRequest, SendResponse, SendStatus},
     NotificationService,
 };
 use tonic::Status;
-use tracing::{debug, instrument, warn};
+use tracing::{debug, instrument};
 
 impl Sender for InAppMessage {
     #[instrument(name = "send-in-app", skip_all)]
-    async fn send(self, svc: NotificationService) -> Result<SendResponse, Status> {
+    async fn send<R: Repo>(
+        self,
+        svc: NotificationService<R>,
+        delivery: Delivery,
+    ) -> Result<SendResponse, Status> {
         let message_id = self.message_id.clone();
-        svc.sender.send(Msg::InApp(self)).await.map_err(|e| {
-            warn!("Failed to send message: {:?}", e);
-            Status::internal("Failed to send message")
-        })?;
-        debug!("Sent in-app notification: {}", message_id);
-        Ok(SendResponse {
-            message_id,
-            timestamp: Some(to_ts()),
-        })
+        let mut devices = vec![self.device_id.clone()];
+        let suppressed = filter_suppressed(&svc, Channel::InApp, &mut devices).await?;
+        let status = if devices.is_empty() {
+            SendStatus::Suppressed
+        } else {
+            svc.dispatch(&message_id, Msg::InApp(self), delivery)
+                .await?
+        };
+        debug!("In-app notification {}: {:?}", message_id, status);
+        Ok(send_response(message_id, status, delivery, suppressed))
     }
 }
 
@@ -31,7 +36,10 @@ impl From<InAppMessage> for Msg {
 impl From<InAppMessage> for SendRequest {
     fn from(in_app: InAppMessage) -> Self {
         let msg: Msg = in_app.into();
-        SendRequest { msg: Some(msg) }
+        SendRequest {
+            msg: Some(msg),
+            ..Default::default()
+        }
     }
 }
 
@@ -1,24 +1,37 @@
-use super::{to_ts, Sender};
+use super::{filter_suppressed, send_response, Delivery, Repo, Sender};
 use crate::{
-    pb::{send_request::Msg, EmailMessage, SendRequest, SendResponse},
+    pb::{send_request::Msg, Channel, EmailMessage, SendRequest, SendResponse, SendStatus},
     NotificationService,
 };
+use crm_core::InvalidArgument;
 use tonic::Status;
-use tracing::{debug, instrument, warn};
+use tracing::{debug, instrument};
 
 impl Sender for EmailMessage {
     #[instrument(name = "send-email", skip_all)]
-    async fn send(self, svc: NotificationService) -> Result<SendResponse, Status> {
+    async fn send<R: Repo>(
+        mut self,
+        svc: NotificationService<R>,
+        delivery: Delivery,
+    ) -> Result<SendResponse, Status> {
         let message_id = self.message_id.clone();
-        svc.sender.send(Msg::Email(self)).await.map_err(|e| {
-            warn!("Failed to send message: {:?}", e);
-            Status::internal("Failed to send message")
-        })?;
-        debug!("Sent email notification: {}", message_id);
-        Ok(SendResponse {
-            message_id,
-            timestamp: Some(to_ts()),
-        })
+        // the attachments are fetched when the email is delivered, report the
+        // errors which can be found without fetching them
+        self.validate()
+            .map_err(|e| InvalidArgument::new(format!("{e:#}")))?;
+
+        let mut suppressed = vec![];
+        for recipients in [&mut self.recipients, &mut self.cc, &mut self.bcc] {
+            suppressed.extend(filter_suppressed(&svc, Channel::Email, recipients).await?);
+        }
+        let status = if self.recipients.is_empty() && self.cc.is_empty() && self.bcc.is_empty() {
+            SendStatus::Suppressed
+        } else {
+            svc.dispatch(&message_id, Msg::Email(self), delivery)
+                .await?
+        };
+        debug!("Email notification {}: {:?}", message_id, status);
+        Ok(send_response(message_id, status, delivery, suppressed))
     }
 }
 
@@ -31,7 +44,10 @@ impl From<EmailMessage> for Msg {
 impl From<EmailMessage> for SendRequest {
     fn from(email: EmailMessage) -> Self {
         let msg: Msg = email.into();
-        SendRequest { msg: Some(msg) }
+        SendRequest {
+            msg: Some(msg),
+            ..Default::default()
+        }
     }
 }
 
@@ -47,6 +63,7 @@ impl EmailMessage {
             recipients: vec![SafeEmail().fake()],
             subject: "Hello".to_string(),
             body: "Hello, world!".to_string(),
+            ..Default::default()
         }
     }
 }
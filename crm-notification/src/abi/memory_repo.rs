@@ -0,0 +1,128 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+
+use super::{Claim, Repo};
+use crate::pb::{Channel, SendResponse, Suppression};
+
+/// An in-memory repository, the suppression list and sent messages are lost when the
+/// service restarts, and messages are only deduplicated within this instance
+#[derive(Debug, Clone, Default)]
+pub struct MemoryRepo {
+    suppressions: Arc<RwLock<HashMap<(i32, String), Suppression>>>,
+    sent: Arc<Mutex<SentMessages>>,
+}
+
+#[derive(Debug, Default)]
+struct SentMessages {
+    /// response of each claimed message id, none while it is being sent
+    messages: HashMap<String, (Option<SendResponse>, DateTime<Utc>)>,
+    /// claimed message ids by the earliest expiration, to purge the expired ones
+    expirations: BinaryHeap<Reverse<(DateTime<Utc>, String)>>,
+}
+
+impl MemoryRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Repo for MemoryRepo {
+    async fn add_suppression(&self, suppression: Suppression) -> Result<Suppression> {
+        let mut suppressions = self.suppressions.write().map_err(|e| anyhow!("{e}"))?;
+        let key = (suppression.channel, suppression.recipient.clone());
+        suppressions.insert(key, suppression.clone());
+        Ok(suppression)
+    }
+
+    async fn remove_suppression(&self, channel: Channel, recipient: &str) -> Result<bool> {
+        let mut suppressions = self.suppressions.write().map_err(|e| anyhow!("{e}"))?;
+        Ok(suppressions
+            .remove(&(channel as i32, recipient.to_string()))
+            .is_some())
+    }
+
+    async fn list_suppressions(&self, channel: Channel) -> Result<Vec<Suppression>> {
+        let suppressions = self.suppressions.read().map_err(|e| anyhow!("{e}"))?;
+        Ok(suppressions
+            .values()
+            .filter(|s| channel == Channel::Unspecified || s.channel == channel as i32)
+            .cloned()
+            .collect())
+    }
+
+    async fn suppressed(&self, channel: Channel, recipients: &[String]) -> Result<Vec<String>> {
+        let suppressions = self.suppressions.read().map_err(|e| anyhow!("{e}"))?;
+        Ok(recipients
+            .iter()
+            .filter(|r| suppressions.contains_key(&(channel as i32, r.to_string())))
+            .cloned()
+            .collect())
+    }
+
+    async fn claim(&self, message_id: &str, expires_at: DateTime<Utc>) -> Result<Claim> {
+        let mut sent = self.sent.lock().map_err(|e| anyhow!("{e}"))?;
+        let now = Utc::now();
+        sent.purge(now);
+
+        match sent.messages.get(message_id) {
+            Some((Some(response), expires_at)) if *expires_at > now => {
+                Ok(Claim::Sent(response.clone()))
+            }
+            Some((None, expires_at)) if *expires_at > now => Ok(Claim::Pending),
+            _ => {
+                sent.messages
+                    .insert(message_id.to_string(), (None, expires_at));
+                sent.expirations
+                    .push(Reverse((expires_at, message_id.to_string())));
+                Ok(Claim::Claimed)
+            }
+        }
+    }
+
+    async fn save_response(
+        &self,
+        response: &SendResponse,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut sent = self.sent.lock().map_err(|e| anyhow!("{e}"))?;
+        let sent = &mut *sent;
+        let message_id = &response.message_id;
+        if let Some(saved) = sent.messages.get_mut(message_id) {
+            *saved = (Some(response.clone()), expires_at);
+            sent.expirations
+                .push(Reverse((expires_at, message_id.clone())));
+        }
+        Ok(())
+    }
+
+    async fn release(&self, message_id: &str) -> Result<()> {
+        let mut sent = self.sent.lock().map_err(|e| anyhow!("{e}"))?;
+        sent.messages.remove(message_id);
+        Ok(())
+    }
+}
+
+impl SentMessages {
+    fn purge(&mut self, now: DateTime<Utc>) {
+        while let Some(Reverse((expires_at, _))) = self.expirations.peek() {
+            if *expires_at > now {
+                break;
+            }
+            let Reverse((expires_at, message_id)) = self.expirations.pop().unwrap();
+            // the id may be claimed again or its response saved after this entry
+            if self
+                .messages
+                .get(&message_id)
+                .is_some_and(|(_, t)| *t == expires_at)
+            {
+                self.messages.remove(&message_id);
+            }
+        }
+    }
+}
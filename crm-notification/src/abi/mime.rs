@@ -0,0 +1,446 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use lettre::message::{
+    header::{ContentType, HeaderName, HeaderValue},
+    Attachment as MimeAttachment, Mailbox, Message, MultiPart, MultiPartBuilder, SinglePart,
+};
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::CONTENT_TYPE,
+    redirect, Client, Url,
+};
+use tracing::{debug, instrument};
+
+use crate::pb::{attachment::Source, Attachment, EmailMessage};
+
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_REDIRECTS: usize = 5;
+/// Attachments larger than this are refused
+const MAX_ATTACHMENT_SIZE: usize = 10 * 1024 * 1024;
+
+/// A client fetching the attachments from the public addresses only, with a timeout,
+/// so that a url can neither reach the internal network nor stall the sender
+pub fn attachment_client() -> Result<Client> {
+    let redirect = redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if let Err(e) = check_url(attempt.url().as_str()) {
+            attempt.error(e)
+        } else {
+            attempt.follow()
+        }
+    });
+    let client = Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(FETCH_TIMEOUT)
+        .redirect(redirect)
+        .dns_resolver(Arc::new(PublicResolver))
+        .build()?;
+    Ok(client)
+}
+
+/// Resolves the hosts to their public addresses, the private ones are dropped
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(anyhow!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Body of a MIME message, either a single part or nested parts
+enum Body {
+    Single(SinglePart),
+    Multi(MultiPart),
+}
+
+impl EmailMessage {
+    /// Fetch the content of the attachments which are given by url, the client should
+    /// be built by [`attachment_client`]
+    #[instrument(name = "fetch-attachments", skip_all)]
+    pub async fn fetch_attachments(&mut self, client: &Client) -> Result<()> {
+        for attachment in self.attachments.iter_mut() {
+            let Some(Source::Url(url)) = &attachment.source else {
+                continue;
+            };
+
+            let mut res = client
+                .get(check_url(url)?)
+                .send()
+                .await
+                .and_then(|res| res.error_for_status())
+                .with_context(|| format!("Failed to fetch attachment {url}"))?;
+            if res
+                .content_length()
+                .is_some_and(|len| len > MAX_ATTACHMENT_SIZE as u64)
+            {
+                bail!("Attachment {url} is too large");
+            }
+            if attachment.content_type.is_empty() {
+                if let Some(content_type) = res.headers().get(CONTENT_TYPE) {
+                    attachment.content_type = content_type.to_str()?.to_string();
+                }
+            }
+            let mut data = vec![];
+            while let Some(chunk) = res.chunk().await? {
+                if data.len() + chunk.len() > MAX_ATTACHMENT_SIZE {
+                    bail!("Attachment {url} is too large");
+                }
+                data.extend_from_slice(&chunk);
+            }
+            debug!("Fetched attachment {}: {} bytes", url, data.len());
+            attachment.source = Some(Source::Data(data));
+        }
+        Ok(())
+    }
+
+    /// Check that the MIME message of the email can be built, without fetching the
+    /// attachments given by url
+    pub fn validate(&self) -> Result<()> {
+        self.build(true).map(|_| ())
+    }
+
+    /// Build the MIME message of the email:
+    ///
+    /// ```text
+    /// multipart/mixed         if there are regular attachments
+    /// └ multipart/related     if there are inline attachments
+    ///   └ multipart/alternative   if html is set, with body as the text fallback
+    /// ```
+    ///
+    /// Attachments given by url must be fetched by [`Self::fetch_attachments`] before.
+    pub fn to_mime(&self) -> Result<Message> {
+        self.build(false)
+    }
+
+    /// Build the MIME message, the attachments given by url are empty if `unfetched`
+    fn build(&self, unfetched: bool) -> Result<Message> {
+        let mut builder = Message::builder()
+            .from(parse_mailbox(&self.sender)?)
+            .subject(&self.subject);
+        for to in &self.recipients {
+            builder = builder.to(parse_mailbox(to)?);
+        }
+        for cc in &self.cc {
+            builder = builder.cc(parse_mailbox(cc)?);
+        }
+        for bcc in &self.bcc {
+            builder = builder.bcc(parse_mailbox(bcc)?);
+        }
+        if !self.reply_to.is_empty() {
+            builder = builder.reply_to(parse_mailbox(&self.reply_to)?);
+        }
+        for (name, value) in &self.headers {
+            let name = HeaderName::new_from_ascii(name.clone())
+                .map_err(|e| anyhow!("Invalid header {name}: {e}"))?;
+            builder = builder.raw_header(HeaderValue::new(name, value.clone()));
+        }
+
+        let mut body = if self.html.is_empty() {
+            Body::Single(SinglePart::plain(self.body.clone()))
+        } else {
+            Body::Multi(MultiPart::alternative_plain_html(
+                self.body.clone(),
+                self.html.clone(),
+            ))
+        };
+
+        let (inline, attached): (Vec<_>, Vec<_>) = self
+            .attachments
+            .iter()
+            .partition(|a| !a.content_id.is_empty());
+        if !inline.is_empty() {
+            let mut related = body.wrap(MultiPart::related());
+            for attachment in inline {
+                related = related.singlepart(attachment.to_part(unfetched)?);
+            }
+            body = Body::Multi(related);
+        }
+        if !attached.is_empty() {
+            let mut mixed = body.wrap(MultiPart::mixed());
+            for attachment in attached {
+                mixed = mixed.singlepart(attachment.to_part(unfetched)?);
+            }
+            body = Body::Multi(mixed);
+        }
+
+        let message = match body {
+            Body::Single(part) => builder.singlepart(part)?,
+            Body::Multi(part) => builder.multipart(part)?,
+        };
+        Ok(message)
+    }
+}
+
+impl Attachment {
+    fn to_part(&self, unfetched: bool) -> Result<SinglePart> {
+        let data = match &self.source {
+            Some(Source::Data(data)) => data.clone(),
+            Some(Source::Url(url)) if unfetched => {
+                check_url(url)?;
+                vec![]
+            }
+            _ => return Err(anyhow!("Attachment {} is not fetched", self.filename)),
+        };
+        let content_type = match self.content_type.as_str() {
+            "" => DEFAULT_CONTENT_TYPE,
+            content_type => content_type,
+        };
+        let content_type = ContentType::parse(content_type)?;
+
+        let attachment = if self.content_id.is_empty() {
+            MimeAttachment::new(self.filename.clone())
+        } else if self.filename.is_empty() {
+            MimeAttachment::new_inline(self.content_id.clone())
+        } else {
+            MimeAttachment::new_inline_with_name(self.content_id.clone(), self.filename.clone())
+        };
+        Ok(attachment.body(data, content_type))
+    }
+}
+
+impl Body {
+    /// Nest the body as the first part of a multipart
+    fn wrap(self, builder: MultiPartBuilder) -> MultiPart {
+        match self {
+            Body::Single(part) => builder.singlepart(part),
+            Body::Multi(part) => builder.multipart(part),
+        }
+    }
+}
+
+/// Parse the url of an attachment, only http(s) urls whose host is not a private
+/// address are allowed, the hosts given by name are checked when resolved
+fn check_url(url: &str) -> Result<Url> {
+    let parsed = Url::parse(url).with_context(|| format!("Invalid attachment url {url}"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        bail!("Invalid attachment url {url}: only http(s) is allowed");
+    }
+    let Some(host) = parsed.host_str() else {
+        bail!("Invalid attachment url {url}: missing host");
+    };
+    // ipv6 hosts are enclosed in brackets
+    let ip = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>();
+    if ip.is_ok_and(|ip| !is_public(ip)) {
+        bail!("Invalid attachment url {url}: private address");
+    }
+    Ok(parsed)
+}
+
+/// Whether the address is outside of the private, loopback and link local networks
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            // 100.64.0.0/10 is shared by carrier-grade NAT
+            let shared = a == 100 && (b & 0xc0) == 64;
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let segment = ip.segments()[0];
+                // fc00::/7 is unique local, fe80::/10 is link local
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || (segment & 0xfe00) == 0xfc00
+                    || (segment & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox> {
+    address
+        .parse()
+        .with_context(|| format!("Invalid address {address}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    fn email() -> EmailMessage {
+        EmailMessage {
+            message_id: "1".to_string(),
+            subject: "Welcome".to_string(),
+            sender: "crm@example.com".to_string(),
+            recipients: vec!["alice@example.com".to_string()],
+            body: "Hello".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn url_attachment(url: String) -> Attachment {
+        Attachment {
+            filename: "image.png".to_string(),
+            source: Some(Source::Url(url)),
+            ..Default::default()
+        }
+    }
+
+    /// Answer every request on a local port with the given response
+    async fn serve(response: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        port
+    }
+
+    fn formatted(email: &EmailMessage) -> String {
+        String::from_utf8(email.to_mime().unwrap().formatted()).unwrap()
+    }
+
+    #[test]
+    fn plain_email_should_be_single_part() {
+        let ret = formatted(&email());
+        assert!(ret.contains("Content-Type: text/plain"));
+        assert!(!ret.contains("multipart"));
+    }
+
+    #[test]
+    fn email_with_html_and_attachments_should_be_multipart() {
+        let mut email = email();
+        email.html = r#"<p>Hello</p><img src="cid:thumbnail">"#.to_string();
+        email.cc = vec!["bob@example.com".to_string()];
+        email.reply_to = "support@example.com".to_string();
+        email.headers.insert(
+            "List-Unsubscribe".to_string(),
+            "<https://unsub>".to_string(),
+        );
+        email.attachments = vec![
+            Attachment {
+                content_type: "image/png".to_string(),
+                source: Some(Source::Data(vec![1, 2, 3])),
+                content_id: "thumbnail".to_string(),
+                ..Default::default()
+            },
+            Attachment {
+                filename: "terms.txt".to_string(),
+                content_type: "text/plain".to_string(),
+                source: Some(Source::Data(b"terms".to_vec())),
+                ..Default::default()
+            },
+        ];
+
+        let ret = formatted(&email);
+        for expected in [
+            "Content-Type: multipart/mixed",
+            "Content-Type: multipart/related",
+            "Content-Type: multipart/alternative",
+            "Content-Type: text/html",
+            "Content-ID: <thumbnail>",
+            "filename=\"terms.txt\"",
+            "Cc: bob@example.com",
+            "Reply-To: support@example.com",
+            "List-Unsubscribe: <https://unsub>",
+        ] {
+            assert!(ret.contains(expected), "missing {expected} in {ret}");
+        }
+    }
+
+    #[test]
+    fn unfetched_attachment_should_fail() {
+        let mut email = email();
+        email.attachments = vec![Attachment {
+            filename: "image.png".to_string(),
+            source: Some(Source::Url("https://placehold.co/1600x900".to_string())),
+            ..Default::default()
+        }];
+
+        assert!(email.to_mime().is_err());
+    }
+
+    #[tokio::test]
+    async fn attachments_should_be_fetched() {
+        let port =
+            serve("HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: 3\r\n\r\nabc")
+                .await;
+        let mut email = email();
+        email.attachments = vec![url_attachment(format!("http://localhost:{port}/image.png"))];
+
+        email.fetch_attachments(&Client::new()).await.unwrap();
+        assert_eq!(email.attachments[0].content_type, "image/png");
+        assert_eq!(
+            email.attachments[0].source,
+            Some(Source::Data(b"abc".to_vec()))
+        );
+        assert!(email.to_mime().is_ok());
+    }
+
+    #[tokio::test]
+    async fn oversized_attachment_should_not_be_fetched() {
+        let port = serve("HTTP/1.1 200 OK\r\nContent-Length: 20000000\r\n\r\n").await;
+        let mut email = email();
+        email.attachments = vec![url_attachment(format!("http://localhost:{port}/image.png"))];
+
+        let err = email.fetch_attachments(&Client::new()).await.unwrap_err();
+        assert!(err.to_string().contains("too large"));
+    }
+
+    #[tokio::test]
+    async fn private_attachment_url_should_be_refused() {
+        let port = serve("HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc").await;
+        let client = attachment_client().unwrap();
+        for url in [
+            format!("http://localhost:{port}/image.png"),
+            format!("http://127.0.0.1:{port}/image.png"),
+            "http://[::1]/image.png".to_string(),
+            "http://10.0.0.1/image.png".to_string(),
+            "file:///etc/passwd".to_string(),
+        ] {
+            let mut email = email();
+            email.attachments = vec![url_attachment(url.clone())];
+            let ret = email.fetch_attachments(&client).await;
+            assert!(ret.is_err(), "{url} should be refused");
+        }
+    }
+
+    #[test]
+    fn email_should_be_validated_without_fetching() {
+        let mut email = email();
+        email.attachments = vec![url_attachment("https://placehold.co/1600x900".to_string())];
+        assert!(email.validate().is_ok());
+
+        email.attachments = vec![url_attachment("http://169.254.169.254/".to_string())];
+        assert!(email.validate().is_err());
+
+        email.attachments.clear();
+        email.recipients = vec!["not an address".to_string()];
+        assert!(email.validate().is_err());
+    }
+}
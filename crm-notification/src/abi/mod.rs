@@ -1,39 +1,123 @@
 mod app;
 mod email;
+mod memory_repo;
+mod mime;
+mod postgres_repo;
+mod scheduler;
 mod sms;
+mod suppression;
 
-use chrono::Utc;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
 use futures::{Stream, StreamExt};
 use prost_types::Timestamp;
 use std::{sync::Arc, time::Duration};
 use tokio::{sync::mpsc, time::sleep};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Response, Status};
-use tracing::{info, instrument, warn};
+use tracing::{debug, info, instrument, warn, Instrument};
 use uuid::Uuid;
 
 use crate::{
+    metrics::{record_message, record_queue_depth},
     pb::{
-        notification_server::NotificationServer, send_request::Msg, EmailMessage, SendRequest,
-        SendResponse,
+        notification_server::NotificationServer, send_request::Msg, CancelRequest, CancelResponse,
+        Channel, EmailMessage, SendRequest, SendResponse, SendStatus, Suppression,
     },
     AppConfig, NotificationService, NotificationServiceInner, ServiceResult,
 };
 
+pub use memory_repo::MemoryRepo;
+pub use postgres_repo::PostgresRepo;
+pub use scheduler::{Delivery, Scheduler};
+
 const CHANNEL_SIZE: usize = 1024;
+/// The longest message id, the size of `sent_messages.message_id`
+const MAX_MESSAGE_ID_LEN: usize = 64;
 
 /// A trait for sending notifications
 pub trait Sender {
-    async fn send(self, svc: NotificationService) -> Result<SendResponse, Status>;
+    async fn send<R: Repo>(
+        self,
+        svc: NotificationService<R>,
+        delivery: Delivery,
+    ) -> Result<SendResponse, Status>;
+}
+
+/// Result of claiming a message id before sending it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Claim {
+    /// The message id is not seen within the idempotency window, the message should be sent
+    Claimed,
+    /// The message is being sent by another request
+    Pending,
+    /// The message is already sent, with the original response
+    Sent(SendResponse),
+}
+
+/// A repository that stores the suppression list of each channel,
+/// and the responses of sent messages to deduplicate them
+pub trait Repo: Send + Sync + 'static {
+    /// Add a recipient to the suppression list, overwriting the reason if it already exists
+    fn add_suppression(
+        &self,
+        suppression: Suppression,
+    ) -> impl std::future::Future<Output = Result<Suppression>> + Send;
+
+    /// Remove a recipient from the suppression list, return whether it existed
+    fn remove_suppression(
+        &self,
+        channel: Channel,
+        recipient: &str,
+    ) -> impl std::future::Future<Output = Result<bool>> + Send;
+
+    /// List the suppression list of the given channel, or of all channels if unspecified
+    fn list_suppressions(
+        &self,
+        channel: Channel,
+    ) -> impl std::future::Future<Output = Result<Vec<Suppression>>> + Send;
+
+    /// Return the recipients which are suppressed on the given channel
+    fn suppressed(
+        &self,
+        channel: Channel,
+        recipients: &[String],
+    ) -> impl std::future::Future<Output = Result<Vec<String>>> + Send;
+
+    /// Claim a message id until `expires_at`, unless it is already claimed and not expired.
+    /// The claim is a short lease, so that a message whose response is never saved can
+    /// be retried once it expired
+    fn claim(
+        &self,
+        message_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> impl std::future::Future<Output = Result<Claim>> + Send;
+
+    /// Save the response of a claimed message, returned to the requests with the same id
+    /// until `expires_at`
+    fn save_response(
+        &self,
+        response: &SendResponse,
+        expires_at: DateTime<Utc>,
+    ) -> impl std::future::Future<Output = Result<()>> + Send;
+
+    /// Release a claimed message id which failed to send, so that it can be retried
+    fn release(&self, message_id: &str) -> impl std::future::Future<Output = Result<()>> + Send;
 }
 
-impl NotificationService {
-    pub fn new(config: AppConfig) -> Self {
+impl<R: Repo> NotificationService<R> {
+    pub fn new(repo: R, config: AppConfig) -> Self {
         // In production, the sender should be a real implementation
         // In general, it had better be a message broker like Kafka, NATS, or RabbitMQ
         // so that we can scale the notification service horizontally
         let sender = dummy_send();
-        let inner = NotificationServiceInner { config, sender };
+        let scheduler = Scheduler::new();
+        let inner = NotificationServiceInner {
+            config,
+            sender,
+            scheduler,
+            repo,
+        };
         Self {
             inner: Arc::new(inner),
         }
@@ -48,6 +132,10 @@ impl NotificationService {
     /// - EmailMessage
     /// - SmsMessage
     /// - InAppMessage
+    ///
+    /// Messages with a `send_at` in the future are held until they are due.
+    /// A message whose id is already sent within the idempotency window is not sent again,
+    /// the original response is returned instead.
     #[instrument(name = "send", skip_all)]
     pub async fn send(
         &self,
@@ -55,26 +143,166 @@ impl NotificationService {
     ) -> ServiceResult<impl Stream<Item = Result<SendResponse, Status>> + Send + 'static> {
         let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
         let notify = self.clone();
-        tokio::spawn(async move {
+        let task = async move {
             while let Some(Ok(req)) = stream.next().await {
-                let notify_clone = notify.clone();
-                let res = match req.msg {
-                    Some(Msg::Email(email)) => email.send(notify_clone).await,
-                    Some(Msg::Sms(sms)) => sms.send(notify_clone).await,
-                    Some(Msg::InApp(in_app)) => in_app.send(notify_clone).await,
-                    None => {
-                        warn!("Invalid request");
-                        Err(Status::invalid_argument("Invalid request"))
-                    }
-                };
+                let res = notify.send_once(req).await;
                 tx.send(res).await.unwrap();
             }
-        });
+        };
+        tokio::spawn(task.in_current_span());
 
         let stream = ReceiverStream::new(rx);
 
         Ok(Response::new(stream))
     }
+
+    /// Send a message unless its id is already claimed within the idempotency window
+    async fn send_once(&self, req: SendRequest) -> Result<SendResponse, Status> {
+        let Some(msg) = req.msg else {
+            warn!("Invalid request");
+            return Err(Status::invalid_argument("Invalid request"));
+        };
+        let delivery = Delivery::try_new(req.send_at, req.ttl)?;
+
+        // messages without id can't be deduplicated
+        let message_id = msg.message_id().to_string();
+        if message_id.is_empty() {
+            return self.send_msg(msg, delivery).await;
+        }
+        if message_id.len() > MAX_MESSAGE_ID_LEN {
+            return Err(Status::invalid_argument(format!(
+                "message_id is longer than {MAX_MESSAGE_ID_LEN} bytes"
+            )));
+        }
+
+        let lease = chrono::Duration::seconds(self.config.server.claim_lease as _);
+        let claim = self
+            .repo
+            .claim(&message_id, Utc::now() + lease)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        match claim {
+            Claim::Claimed => {}
+            Claim::Sent(res) => {
+                info!("Message {} is already sent", message_id);
+                return Ok(res);
+            }
+            Claim::Pending => {
+                return Err(Status::aborted(format!(
+                    "Message {message_id} is being sent"
+                )))
+            }
+        }
+
+        let res = self.send_msg(msg, delivery).await;
+        let saved = match &res {
+            Ok(res) => {
+                let window = chrono::Duration::seconds(self.config.server.idempotency_window as _);
+                self.repo.save_response(res, Utc::now() + window).await
+            }
+            Err(_) => self.repo.release(&message_id).await,
+        };
+        saved.map_err(|e| Status::internal(e.to_string()))?;
+        res
+    }
+
+    async fn send_msg(&self, msg: Msg, delivery: Delivery) -> Result<SendResponse, Status> {
+        let svc = self.clone();
+        match msg {
+            Msg::Email(email) => email.send(svc, delivery).await,
+            Msg::Sms(sms) => sms.send(svc, delivery).await,
+            Msg::InApp(in_app) => in_app.send(svc, delivery).await,
+        }
+    }
+
+    /// Cancel a scheduled message which is not sent yet, its id is released so
+    /// that the message can be sent again
+    #[instrument(name = "cancel", skip_all)]
+    pub async fn cancel(&self, req: CancelRequest) -> ServiceResult<CancelResponse> {
+        let cancelled = self.scheduler.cancel(&req.message_id);
+        info!("Cancel scheduled message {}: {}", req.message_id, cancelled);
+        if cancelled {
+            self.repo
+                .release(&req.message_id)
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?;
+        }
+        Ok(Response::new(CancelResponse { cancelled }))
+    }
+
+    /// Dispatch the message now, or hold it in the scheduler until it is due
+    #[instrument(name = "dispatch", skip(self, msg, delivery))]
+    async fn dispatch(
+        &self,
+        message_id: &str,
+        msg: Msg,
+        delivery: Delivery,
+    ) -> Result<SendStatus, Status> {
+        if delivery.is_expired() {
+            return Ok(SendStatus::Expired);
+        }
+
+        if delivery.is_delayed() {
+            let svc = self.clone();
+            let due = async move { svc.send_due(msg).await }.in_current_span();
+            self.scheduler
+                .schedule(message_id.to_string(), delivery, due);
+            return Ok(SendStatus::Scheduled);
+        }
+
+        let channel = msg.channel();
+        self.sender.send(msg).await.map_err(|e| {
+            warn!("Failed to send message: {:?}", e);
+            record_message(channel, false);
+            Status::internal("Failed to send message")
+        })?;
+        Ok(SendStatus::Sent)
+    }
+
+    /// Send a scheduled message once it is due, the recipients suppressed since it
+    /// was accepted are dropped
+    async fn send_due(&self, mut msg: Msg) {
+        let message_id = msg.message_id().to_string();
+        let channel = msg.channel();
+        match retain_unsuppressed(self, &mut msg).await {
+            Ok(true) => {}
+            Ok(false) => {
+                info!("Scheduled message {} is suppressed", message_id);
+                return;
+            }
+            Err(e) => {
+                warn!("Failed to filter scheduled message {}: {:?}", message_id, e);
+                record_message(channel, false);
+                return;
+            }
+        }
+
+        match self.sender.send(msg).await {
+            Ok(_) => debug!("Sent scheduled message: {}", message_id),
+            Err(e) => {
+                warn!("Failed to send scheduled message: {:?}", e);
+                record_message(channel, false);
+            }
+        }
+    }
+}
+
+impl Msg {
+    pub fn message_id(&self) -> &str {
+        match self {
+            Msg::Email(email) => &email.message_id,
+            Msg::Sms(sms) => &sms.message_id,
+            Msg::InApp(in_app) => &in_app.message_id,
+        }
+    }
+
+    pub fn channel(&self) -> Channel {
+        match self {
+            Msg::Email(_) => Channel::Email,
+            Msg::Sms(_) => Channel::Sms,
+            Msg::InApp(_) => Channel::InApp,
+        }
+    }
 }
 
 impl SendRequest {
@@ -85,25 +313,133 @@ impl SendRequest {
             subject,
             sender,
             recipients: recipients.to_vec(),
-            body: "".to_string(),
+            ..Default::default()
         });
 
-        SendRequest { msg: Some(msg) }
+        SendRequest {
+            msg: Some(msg),
+            ..Default::default()
+        }
     }
 }
 
 fn dummy_send() -> mpsc::Sender<Msg> {
-    let (tx, mut rx) = mpsc::channel(CHANNEL_SIZE * 100);
+    let (tx, mut rx) = mpsc::channel::<Msg>(CHANNEL_SIZE * 100);
+
+    let client = mime::attachment_client().expect("attachment client is built");
 
     tokio::spawn(async move {
         while let Some(msg) = rx.recv().await {
-            info!("Received message: {:?}", msg);
+            record_queue_depth(rx.len());
+            let channel = msg.channel();
+            let sent = match msg {
+                Msg::Email(email) => {
+                    let message_id = email.message_id.clone();
+                    match build_email(email, &client).await {
+                        Ok(mime) => {
+                            info!(
+                                "Received email {}: {} bytes",
+                                message_id,
+                                mime.formatted().len()
+                            );
+                            true
+                        }
+                        Err(e) => {
+                            warn!("Failed to build email {}: {:?}", message_id, e);
+                            false
+                        }
+                    }
+                }
+                msg => {
+                    info!("Received message: {:?}", msg);
+                    true
+                }
+            };
+            record_message(channel, sent);
             sleep(Duration::from_millis(300)).await;
         }
     });
     tx
 }
 
+/// Fetch the attachments of the email and build its MIME message
+async fn build_email(mut email: EmailMessage, client: &reqwest::Client) -> Result<lettre::Message> {
+    email.fetch_attachments(client).await?;
+    email.to_mime()
+}
+
+/// Remove the suppressed recipients of the channel from `recipients`,
+/// return the removed ones
+async fn filter_suppressed<R: Repo>(
+    svc: &NotificationService<R>,
+    channel: Channel,
+    recipients: &mut Vec<String>,
+) -> Result<Vec<String>, Status> {
+    let normalized: Vec<_> = recipients.iter().map(|r| normalize(channel, r)).collect();
+    let suppressed = svc
+        .repo
+        .suppressed(channel, &normalized)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+    recipients.retain(|r| !suppressed.contains(&normalize(channel, r)));
+    Ok(suppressed)
+}
+
+/// Remove the suppressed recipients of the message, return whether any recipient is left
+async fn retain_unsuppressed<R: Repo>(
+    svc: &NotificationService<R>,
+    msg: &mut Msg,
+) -> Result<bool, Status> {
+    match msg {
+        Msg::Email(email) => {
+            for recipients in [&mut email.recipients, &mut email.cc, &mut email.bcc] {
+                filter_suppressed(svc, Channel::Email, recipients).await?;
+            }
+            Ok(!(email.recipients.is_empty() && email.cc.is_empty() && email.bcc.is_empty()))
+        }
+        Msg::Sms(sms) => {
+            filter_suppressed(svc, Channel::Sms, &mut sms.recipients).await?;
+            Ok(!sms.recipients.is_empty())
+        }
+        Msg::InApp(in_app) => {
+            let mut devices = vec![in_app.device_id.clone()];
+            filter_suppressed(svc, Channel::InApp, &mut devices).await?;
+            Ok(!devices.is_empty())
+        }
+    }
+}
+
+/// Build the response of a message according to how it was dispatched
+fn send_response(
+    message_id: String,
+    status: SendStatus,
+    delivery: Delivery,
+    suppressed: Vec<String>,
+) -> SendResponse {
+    let timestamp = match (status, delivery.send_at) {
+        (SendStatus::Scheduled, Some(send_at)) => Timestamp {
+            seconds: send_at.timestamp(),
+            nanos: send_at.timestamp_subsec_nanos() as i32,
+        },
+        _ => to_ts(),
+    };
+
+    SendResponse {
+        message_id,
+        timestamp: Some(timestamp),
+        status: status as _,
+        suppressed,
+    }
+}
+
+/// Normalize a recipient so that lookups are not case sensitive for emails
+fn normalize(channel: Channel, recipient: &str) -> String {
+    match channel {
+        Channel::Email => recipient.trim().to_lowercase(),
+        _ => recipient.trim().to_string(),
+    }
+}
+
 fn to_ts() -> Timestamp {
     let now = Utc::now();
     Timestamp {
@@ -111,3 +447,68 @@ fn to_ts() -> Timestamp {
         nanos: now.timestamp_subsec_nanos() as i32,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::{SmsMessage, SuppressionReason};
+    use crm_core::ConfigExt;
+
+    fn service(sender: mpsc::Sender<Msg>) -> NotificationService<MemoryRepo> {
+        let inner = NotificationServiceInner {
+            config: AppConfig::load().unwrap(),
+            sender,
+            scheduler: Scheduler::new(),
+            repo: MemoryRepo::new(),
+        };
+        NotificationService {
+            inner: Arc::new(inner),
+        }
+    }
+
+    #[tokio::test]
+    async fn recipients_suppressed_before_due_should_be_dropped() -> Result<()> {
+        let (tx, mut rx) = mpsc::channel(1);
+        let svc = service(tx);
+        svc.repo
+            .add_suppression(Suppression {
+                channel: Channel::Sms as _,
+                recipient: "+10000000001".to_string(),
+                reason: SuppressionReason::Unsubscribed as _,
+                created_at: None,
+            })
+            .await?;
+
+        let sms = SmsMessage {
+            message_id: "1".to_string(),
+            recipients: vec!["+10000000001".to_string(), "+10000000002".to_string()],
+            ..Default::default()
+        };
+        svc.send_due(Msg::Sms(sms.clone())).await;
+        let Some(Msg::Sms(sent)) = rx.recv().await else {
+            panic!("expected an sms");
+        };
+        assert_eq!(sent.recipients, vec!["+10000000002"]);
+
+        let sms = SmsMessage {
+            recipients: vec!["+10000000001".to_string()],
+            ..sms
+        };
+        svc.send_due(Msg::Sms(sms)).await;
+        assert!(rx.try_recv().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn too_long_message_id_should_be_rejected() {
+        let (tx, _rx) = mpsc::channel(1);
+        let svc = service(tx);
+        let sms = SmsMessage {
+            message_id: "x".repeat(MAX_MESSAGE_ID_LEN + 1),
+            ..SmsMessage::fake()
+        };
+
+        let err = svc.send_once(SendRequest::from(sms)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,232 @@
+use anyhow::Result;
+use chrono::{DateTime, TimeZone as _, Utc};
+use crm_core::health::Ping;
+use prost::Message;
+use prost_types::Timestamp;
+use sqlx::PgPool;
+use tracing::instrument;
+
+use super::{Claim, Repo};
+use crate::pb::{Channel, SendResponse, Suppression, SuppressionReason};
+
+/// A repository backed by postgres, see `migrations` for the schema
+#[derive(Clone)]
+pub struct PostgresRepo {
+    pool: PgPool,
+}
+
+#[derive(sqlx::FromRow)]
+struct SuppressionRow {
+    channel: String,
+    recipient: String,
+    reason: String,
+    created_at: DateTime<Utc>,
+}
+
+impl PostgresRepo {
+    pub async fn new(url: &str) -> Result<Self> {
+        let pool = PgPool::connect(url).await?;
+        Ok(Self { pool })
+    }
+}
+
+impl Ping for PostgresRepo {
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+impl Repo for PostgresRepo {
+    #[instrument(name = "add-suppression-postgres", skip_all)]
+    async fn add_suppression(&self, suppression: Suppression) -> Result<Suppression> {
+        let row = sqlx::query_as::<_, SuppressionRow>(
+            "INSERT INTO suppressions(channel, recipient, reason, created_at)
+            VALUES ($1::channel, $2, $3::suppression_reason, $4)
+            ON CONFLICT (channel, recipient) DO UPDATE SET reason = EXCLUDED.reason
+            RETURNING channel::text, recipient, reason::text, created_at",
+        )
+        .bind(channel_name(suppression.channel()))
+        .bind(&suppression.recipient)
+        .bind(reason_name(suppression.reason()))
+        .bind(
+            suppression
+                .created_at
+                .as_ref()
+                .map(ts_to_utc)
+                .unwrap_or_else(Utc::now),
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.into())
+    }
+
+    #[instrument(name = "remove-suppression-postgres", skip_all)]
+    async fn remove_suppression(&self, channel: Channel, recipient: &str) -> Result<bool> {
+        let ret =
+            sqlx::query("DELETE FROM suppressions WHERE channel = $1::channel AND recipient = $2")
+                .bind(channel_name(channel))
+                .bind(recipient)
+                .execute(&self.pool)
+                .await?;
+
+        Ok(ret.rows_affected() > 0)
+    }
+
+    #[instrument(name = "list-suppressions-postgres", skip_all)]
+    async fn list_suppressions(&self, channel: Channel) -> Result<Vec<Suppression>> {
+        // an unspecified channel binds NULL and lists all channels
+        let channel = (channel != Channel::Unspecified).then(|| channel_name(channel));
+        let query = sqlx::query_as::<_, SuppressionRow>(
+            "SELECT channel::text, recipient, reason::text, created_at FROM suppressions
+            WHERE $1::text IS NULL OR channel = $1::channel",
+        )
+        .bind(channel);
+        let ret = query.fetch_all(&self.pool).await?;
+
+        Ok(ret.into_iter().map(|row| row.into()).collect())
+    }
+
+    #[instrument(name = "suppressed-postgres", skip_all)]
+    async fn suppressed(&self, channel: Channel, recipients: &[String]) -> Result<Vec<String>> {
+        if recipients.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let ret = sqlx::query_scalar::<_, String>(
+            "SELECT recipient FROM suppressions WHERE channel = $1::channel AND recipient = ANY($2)",
+        )
+        .bind(channel_name(channel))
+        .bind(recipients)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ret)
+    }
+
+    #[instrument(name = "claim-postgres", skip_all)]
+    async fn claim(&self, message_id: &str, expires_at: DateTime<Utc>) -> Result<Claim> {
+        // insert the claim, or take over an expired one, purging the other expired ids
+        let claimed = sqlx::query(
+            "WITH purged AS (
+                DELETE FROM sent_messages WHERE expires_at <= now() AND message_id <> $1
+            )
+            INSERT INTO sent_messages(message_id, expires_at) VALUES ($1, $2)
+            ON CONFLICT (message_id) DO UPDATE
+            SET response = NULL, expires_at = EXCLUDED.expires_at
+            WHERE sent_messages.expires_at <= now()",
+        )
+        .bind(message_id)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0;
+        if claimed {
+            return Ok(Claim::Claimed);
+        }
+
+        let response = sqlx::query_scalar::<_, Option<Vec<u8>>>(
+            "SELECT response FROM sent_messages WHERE message_id = $1",
+        )
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .flatten();
+
+        match response {
+            Some(response) => Ok(Claim::Sent(SendResponse::decode(response.as_slice())?)),
+            None => Ok(Claim::Pending),
+        }
+    }
+
+    #[instrument(name = "save-response-postgres", skip_all)]
+    async fn save_response(
+        &self,
+        response: &SendResponse,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE sent_messages SET response = $2, expires_at = $3 WHERE message_id = $1",
+        )
+        .bind(&response.message_id)
+        .bind(response.encode_to_vec())
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(name = "release-postgres", skip_all)]
+    async fn release(&self, message_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM sent_messages WHERE message_id = $1")
+            .bind(message_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+impl From<SuppressionRow> for Suppression {
+    fn from(row: SuppressionRow) -> Self {
+        let channel = Channel::from_str_name(&format!("CHANNEL_{}", row.channel.to_uppercase()))
+            .unwrap_or_default();
+        let reason = SuppressionReason::from_str_name(&format!(
+            "SUPPRESSION_REASON_{}",
+            row.reason.to_uppercase()
+        ))
+        .unwrap_or_default();
+
+        Suppression {
+            channel: channel as _,
+            recipient: row.recipient,
+            reason: reason as _,
+            created_at: Some(Timestamp {
+                seconds: row.created_at.timestamp(),
+                nanos: row.created_at.timestamp_subsec_nanos() as i32,
+            }),
+        }
+    }
+}
+
+/// Name of the `channel` postgres enum value, e.g. `in_app`
+fn channel_name(channel: Channel) -> String {
+    channel
+        .as_str_name()
+        .trim_start_matches("CHANNEL_")
+        .to_lowercase()
+}
+
+/// Name of the `suppression_reason` postgres enum value, e.g. `hard_bounce`
+fn reason_name(reason: SuppressionReason) -> String {
+    reason
+        .as_str_name()
+        .trim_start_matches("SUPPRESSION_REASON_")
+        .to_lowercase()
+}
+
+fn ts_to_utc(ts: &Timestamp) -> DateTime<Utc> {
+    Utc.timestamp_opt(ts.seconds, ts.nanos as _).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enum_names_should_match_postgres_types() {
+        assert_eq!(channel_name(Channel::InApp), "in_app");
+        assert_eq!(reason_name(SuppressionReason::HardBounce), "hard_bounce");
+
+        let row = SuppressionRow {
+            channel: "in_app".to_string(),
+            recipient: "device".to_string(),
+            reason: "hard_bounce".to_string(),
+            created_at: Utc::now(),
+        };
+        let suppression: Suppression = row.into();
+        assert_eq!(suppression.channel(), Channel::InApp);
+        assert_eq!(suppression.reason(), SuppressionReason::HardBounce);
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, TimeZone as _, Utc};
+use crm_core::InvalidArgument;
+use prost_types::{Duration, Timestamp};
+use tokio::{task::AbortHandle, time::sleep};
+use tracing::warn;
+
+/// When a message should be dispatched, parsed from the `send_at` and `ttl` of a request
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Delivery {
+    pub send_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Holds delayed messages and dispatches them once they are due.
+///
+/// Scheduled messages are kept in the memory of this instance, so a message can
+/// only be cancelled on the instance which accepted it.
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    pending: Arc<Mutex<HashMap<String, AbortHandle>>>,
+}
+
+impl Delivery {
+    pub fn try_new(
+        send_at: Option<Timestamp>,
+        ttl: Option<Duration>,
+    ) -> Result<Self, InvalidArgument> {
+        let send_at = send_at
+            .map(|ts| {
+                Utc.timestamp_opt(ts.seconds, ts.nanos as _)
+                    .single()
+                    .ok_or(InvalidArgument::new("Invalid send_at"))
+            })
+            .transpose()?;
+
+        let ttl = ttl
+            .map(|ttl| {
+                std::time::Duration::try_from(ttl)
+                    .ok()
+                    .and_then(|ttl| chrono::Duration::from_std(ttl).ok())
+                    .ok_or(InvalidArgument::new("Invalid ttl"))
+            })
+            .transpose()?;
+
+        let expires_at = ttl.map(|ttl| send_at.unwrap_or_else(Utc::now) + ttl);
+
+        Ok(Self {
+            send_at,
+            expires_at,
+        })
+    }
+
+    /// Whether the message is due later than now
+    pub fn is_delayed(&self) -> bool {
+        self.send_at.is_some_and(|t| t > Utc::now())
+    }
+
+    /// Whether the message may no longer be sent
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|t| t < Utc::now())
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold the message until `delivery.send_at` and `dispatch` it then unless it expired,
+    /// replacing the one scheduled with the same id
+    pub fn schedule<F>(&self, message_id: String, delivery: Delivery, dispatch: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let delay = delivery
+            .send_at
+            .and_then(|t| (t - Utc::now()).to_std().ok())
+            .unwrap_or_default();
+
+        let scheduler = self.clone();
+        let id = message_id.clone();
+        let task = async move {
+            sleep(delay).await;
+            if !id.is_empty() {
+                scheduler.remove(&id);
+            }
+
+            if delivery.is_expired() {
+                warn!("Scheduled message {} expired", id);
+                return;
+            }
+            dispatch.await;
+        };
+
+        // messages without id can't be cancelled, and must not replace each other
+        if message_id.is_empty() {
+            tokio::spawn(task);
+            return;
+        }
+
+        // hold the lock until the task is registered, so that it cannot remove itself before
+        let mut pending = self.lock();
+        let handle = tokio::spawn(task);
+        if let Some(previous) = pending.insert(message_id, handle.abort_handle()) {
+            previous.abort();
+        }
+    }
+
+    /// Cancel a scheduled message, return whether it was still scheduled
+    pub fn cancel(&self, message_id: &str) -> bool {
+        match self.remove(message_id) {
+            Some(handle) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove(&self, message_id: &str) -> Option<AbortHandle> {
+        self.lock().remove(message_id)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, AbortHandle>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::{send_request::Msg, InAppMessage};
+    use std::time::Duration as StdDuration;
+    use tokio::{sync::mpsc, time::timeout};
+
+    fn delivery_in(millis: i64) -> Delivery {
+        Delivery {
+            send_at: Some(Utc::now() + chrono::Duration::milliseconds(millis)),
+            expires_at: None,
+        }
+    }
+
+    fn send_to(tx: &mpsc::Sender<Msg>, msg: Msg) -> impl Future<Output = ()> {
+        let tx = tx.clone();
+        async move { tx.send(msg).await.unwrap() }
+    }
+
+    #[tokio::test]
+    async fn scheduled_message_should_be_sent_when_due() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let scheduler = Scheduler::new();
+        let msg = Msg::InApp(InAppMessage::default());
+
+        scheduler.schedule("1".to_string(), delivery_in(50), send_to(&tx, msg.clone()));
+        assert!(rx.try_recv().is_err());
+
+        let ret = timeout(StdDuration::from_secs(1), rx.recv()).await.unwrap();
+        assert_eq!(ret, Some(msg));
+        assert!(!scheduler.cancel("1"));
+    }
+
+    #[tokio::test]
+    async fn cancelled_message_should_not_be_sent() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let scheduler = Scheduler::new();
+
+        let msg = Msg::InApp(InAppMessage::default());
+        scheduler.schedule("1".to_string(), delivery_in(50), send_to(&tx, msg));
+        assert!(scheduler.cancel("1"));
+
+        let ret = timeout(StdDuration::from_millis(200), rx.recv()).await;
+        assert!(ret.is_err());
+    }
+
+    #[tokio::test]
+    async fn messages_without_id_should_all_be_sent() {
+        let (tx, mut rx) = mpsc::channel(2);
+        let scheduler = Scheduler::new();
+
+        for body in ["first", "second"] {
+            let msg = Msg::InApp(InAppMessage {
+                body: body.to_string(),
+                ..Default::default()
+            });
+            scheduler.schedule(String::new(), delivery_in(50), send_to(&tx, msg));
+        }
+        assert!(!scheduler.cancel(""));
+
+        let mut bodies = vec![];
+        for _ in 0..2 {
+            let ret = timeout(StdDuration::from_secs(1), rx.recv()).await.unwrap();
+            let Some(Msg::InApp(msg)) = ret else {
+                panic!("expected an in-app message");
+            };
+            bodies.push(msg.body);
+        }
+        bodies.sort();
+        assert_eq!(bodies, ["first", "second"]);
+    }
+
+    #[test]
+    fn delivery_should_expire_after_ttl() {
+        let send_at = Utc::now() - chrono::Duration::seconds(10);
+        let delivery = Delivery::try_new(
+            Some(Timestamp {
+                seconds: send_at.timestamp(),
+                nanos: 0,
+            }),
+            Some(Duration {
+                seconds: 1,
+                nanos: 0,
+            }),
+        )
+        .unwrap();
+
+        assert!(!delivery.is_delayed());
+        assert!(delivery.is_expired());
+    }
+}
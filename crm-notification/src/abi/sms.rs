@@ -1,24 +1,27 @@
-use super::{to_ts, Sender};
+use super::{filter_suppressed, send_response, Delivery, Repo, Sender};
 use crate::{
-    pb::{send_request::Msg, SendRequest, SendResponse, SmsMessage},
+    pb::{send_request::Msg, Channel, SendRequest, SendResponse, SendStatus, SmsMessage},
     NotificationService,
 };
 use tonic::Status;
-use tracing::{debug, instrument, warn};
+use tracing::{debug, instrument};
 
 impl Sender for SmsMessage {
     #[instrument(name = "send-sms", skip_all)]
-    async fn send(self, svc: NotificationService) -> Result<SendResponse, Status> {
+    async fn send<R: Repo>(
+        mut self,
+        svc: NotificationService<R>,
+        delivery: Delivery,
+    ) -> Result<SendResponse, Status> {
         let message_id = self.message_id.clone();
-        svc.sender.send(Msg::Sms(self)).await.map_err(|e| {
-            warn!("Failed to send message: {:?}", e);
-            Status::internal("Failed to send message")
-        })?;
-        debug!("Sent SMS notification: {}", message_id);
-        Ok(SendResponse {
-            message_id,
-            timestamp: Some(to_ts()),
-        })
+        let suppressed = filter_suppressed(&svc, Channel::Sms, &mut self.recipients).await?;
+        let status = if self.recipients.is_empty() {
+            SendStatus::Suppressed
+        } else {
+            svc.dispatch(&message_id, Msg::Sms(self), delivery).await?
+        };
+        debug!("SMS notification {}: {:?}", message_id, status);
+        Ok(send_response(message_id, status, delivery, suppressed))
     }
 }
 
@@ -31,7 +34,10 @@ impl From<SmsMessage> for Msg {
 impl From<SmsMessage> for SendRequest {
     fn from(sms: SmsMessage) -> Self {
         let msg: Msg = sms.into();
-        SendRequest { msg: Some(msg) }
+        SendRequest {
+            msg: Some(msg),
+            ..Default::default()
+        }
     }
 }
 
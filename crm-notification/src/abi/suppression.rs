@@ -0,0 +1,93 @@
+use super::{normalize, to_ts, Repo};
+use crate::{
+    pb::{
+        AddSuppressionRequest, Channel, ListSuppressionsRequest, RemoveSuppressionRequest,
+        RemoveSuppressionResponse, Suppression,
+    },
+    NotificationService, ServiceResult,
+};
+use crm_core::InvalidArgument;
+use futures::{stream, Stream};
+use tonic::{Response, Status};
+use tracing::{info, instrument};
+
+impl<R: Repo> NotificationService<R> {
+    /// Add a recipient to the suppression list, e.g. when the user unsubscribed
+    /// or the address hard bounced
+    #[instrument(name = "add-suppression", skip_all)]
+    pub async fn add_suppression(&self, req: AddSuppressionRequest) -> ServiceResult<Suppression> {
+        let channel = specified_channel(req.channel)?;
+        let recipient = validate_recipient(channel, &req.recipient)?;
+
+        let suppression = Suppression {
+            channel: channel as _,
+            recipient,
+            reason: req.reason,
+            created_at: Some(to_ts()),
+        };
+        let suppression = self
+            .repo
+            .add_suppression(suppression)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        info!(
+            "Suppressed {} on channel {:?}",
+            suppression.recipient, channel
+        );
+
+        Ok(Response::new(suppression))
+    }
+
+    /// Remove a recipient from the suppression list
+    #[instrument(name = "remove-suppression", skip_all)]
+    pub async fn remove_suppression(
+        &self,
+        req: RemoveSuppressionRequest,
+    ) -> ServiceResult<RemoveSuppressionResponse> {
+        let channel = specified_channel(req.channel)?;
+        let recipient = validate_recipient(channel, &req.recipient)?;
+
+        let removed = self
+            .repo
+            .remove_suppression(channel, &recipient)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(Response::new(RemoveSuppressionResponse { removed }))
+    }
+
+    /// List the suppression list of a channel, or of all channels if unspecified
+    #[instrument(name = "list-suppressions", skip_all)]
+    pub async fn list_suppressions(
+        &self,
+        req: ListSuppressionsRequest,
+    ) -> ServiceResult<impl Stream<Item = Result<Suppression, Status>> + Send + 'static> {
+        let channel = Channel::try_from(req.channel)
+            .map_err(|_| Status::invalid_argument("Invalid channel"))?;
+
+        let suppressions = self
+            .repo
+            .list_suppressions(channel)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(Response::new(stream::iter(
+            suppressions.into_iter().map(Ok),
+        )))
+    }
+}
+
+fn specified_channel(channel: i32) -> Result<Channel, InvalidArgument> {
+    match Channel::try_from(channel) {
+        Ok(Channel::Unspecified) | Err(_) => Err(InvalidArgument::new("Invalid channel")),
+        Ok(channel) => Ok(channel),
+    }
+}
+
+fn validate_recipient(channel: Channel, recipient: &str) -> Result<String, InvalidArgument> {
+    let recipient = normalize(channel, recipient);
+    if recipient.is_empty() {
+        return Err(InvalidArgument::new("Recipient is required"));
+    }
+    Ok(recipient)
+}
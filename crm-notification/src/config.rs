@@ -1,4 +1,4 @@
-use crm_core::telemetry;
+use crm_core::{auth::AuthConfig, telemetry, TlsConfig};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -8,12 +8,27 @@ pub struct AppConfig {
     pub telemetry: telemetry::Config,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AuthConfig {
-    pub pk: String,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub port: u16,
+    /// Postgres url of the suppression list and sent messages, kept in memory if not set.
+    /// Set it to deduplicate messages across instances.
+    pub db_url: Option<String>,
+    /// Seconds in which a message with the same id is sent only once
+    #[serde(default = "default_idempotency_window")]
+    pub idempotency_window: u64,
+    /// Seconds a message id stays claimed while it is being sent, a message whose
+    /// response is never saved can be retried after it
+    #[serde(default = "default_claim_lease")]
+    pub claim_lease: u64,
+    /// serve over TLS if set
+    pub tls: Option<TlsConfig>,
+}
+
+fn default_idempotency_window() -> u64 {
+    60 * 60 * 24
+}
+
+fn default_claim_lease() -> u64 {
+    60
 }
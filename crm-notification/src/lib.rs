@@ -4,32 +4,43 @@ pub mod pb;
 
 mod abi;
 mod config;
+mod metrics;
 
 use std::{ops::Deref, sync::Arc};
 
+use abi::Scheduler;
+pub use abi::{Claim, MemoryRepo, PostgresRepo, Repo};
 pub use config::AppConfig;
 use futures::Stream;
-use pb::{notification_server::Notification, send_request::Msg, SendRequest, SendResponse};
+use pb::{
+    notification_server::Notification, send_request::Msg, AddSuppressionRequest, CancelRequest,
+    CancelResponse, ListSuppressionsRequest, RemoveSuppressionRequest, RemoveSuppressionResponse,
+    SendRequest, SendResponse, Suppression,
+};
 use tokio::sync::mpsc;
 use tonic::{async_trait, Request, Response, Status, Streaming};
 use tracing::instrument;
 
-#[derive(Clone)]
-pub struct NotificationService {
-    inner: Arc<NotificationServiceInner>,
+pub struct NotificationService<R> {
+    inner: Arc<NotificationServiceInner<R>>,
 }
 
 #[allow(unused)]
-pub struct NotificationServiceInner {
+pub struct NotificationServiceInner<R> {
     config: AppConfig,
     sender: mpsc::Sender<Msg>,
+    /// Holds the messages which are sent later
+    scheduler: Scheduler,
+    /// Storage of the suppression list
+    repo: R,
 }
 
 type ServiceResult<T> = Result<Response<T>, Status>;
 
 #[async_trait]
-impl Notification for NotificationService {
+impl<R: Repo> Notification for NotificationService<R> {
     type SendStream = impl Stream<Item = Result<SendResponse, Status>> + Send + 'static;
+    type ListSuppressionsStream = impl Stream<Item = Result<Suppression, Status>> + Send + 'static;
 
     #[instrument(name = "send-handler", skip(self, request))]
     async fn send(
@@ -39,10 +50,47 @@ impl Notification for NotificationService {
         let stream = request.into_inner();
         self.send(stream).await
     }
+
+    #[instrument(name = "cancel-handler", skip_all)]
+    async fn cancel(&self, request: Request<CancelRequest>) -> ServiceResult<CancelResponse> {
+        self.cancel(request.into_inner()).await
+    }
+
+    #[instrument(name = "add-suppression-handler", skip_all)]
+    async fn add_suppression(
+        &self,
+        request: Request<AddSuppressionRequest>,
+    ) -> ServiceResult<Suppression> {
+        self.add_suppression(request.into_inner()).await
+    }
+
+    #[instrument(name = "remove-suppression-handler", skip_all)]
+    async fn remove_suppression(
+        &self,
+        request: Request<RemoveSuppressionRequest>,
+    ) -> ServiceResult<RemoveSuppressionResponse> {
+        self.remove_suppression(request.into_inner()).await
+    }
+
+    #[instrument(name = "list-suppressions-handler", skip_all)]
+    async fn list_suppressions(
+        &self,
+        request: Request<ListSuppressionsRequest>,
+    ) -> ServiceResult<Self::ListSuppressionsStream> {
+        self.list_suppressions(request.into_inner()).await
+    }
+}
+
+impl<R> Clone for NotificationService<R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
 }
 
-impl Deref for NotificationService {
-    type Target = NotificationServiceInner;
+impl<R> Deref for NotificationService<R> {
+    type Target = NotificationServiceInner<R>;
 
     fn deref(&self) -> &Self::Target {
         &self.inner
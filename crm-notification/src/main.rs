@@ -1,8 +1,18 @@
 use std::mem;
 
 use anyhow::Result;
-use crm_core::{accept_trace, log_error, make_span, telemetry, ConfigExt};
-use crm_notification::{AppConfig, NotificationService};
+use crm_core::{
+    accept_trace,
+    auth::AuthLayer,
+    health::{report_health, report_ping},
+    log_error, make_span,
+    metrics::GrpcMetricsLayer,
+    telemetry, ConfigExt,
+};
+use crm_notification::{
+    pb::{notification_server::SERVICE_NAME, FILE_DESCRIPTOR_SET},
+    AppConfig, MemoryRepo, NotificationService, PostgresRepo,
+};
 use tonic::transport::Server;
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
@@ -20,22 +30,54 @@ async fn main() -> Result<()> {
     let addr = format!("127.0.0.1:{}", addr).parse().unwrap();
     info!("Notification service listening on {}", addr);
 
-    let svc = NotificationService::new(config).into_server();
-
-    Server::builder()
-        .layer(
-            ServiceBuilder::new()
-                .layer(tower::timeout::TimeoutLayer::new(
-                    std::time::Duration::from_secs(30),
-                ))
-                .layer(
-                    TraceLayer::new_for_grpc()
-                        .make_span_with(make_span)
-                        .on_request(accept_trace),
-                ),
-        )
-        .add_service(svc)
-        .serve(addr)
-        .await?;
+    let dk = config.auth.decoding_key()?;
+    let authz = config.auth.authz_layer();
+
+    let server = match &config.server.tls {
+        Some(tls) => Server::builder().tls_config(tls.server_config()?)?,
+        None => {
+            info!("TLS is not enabled");
+            Server::builder()
+        }
+    };
+    let mut server = server.layer(
+        ServiceBuilder::new()
+            .layer(GrpcMetricsLayer::new(&[
+                FILE_DESCRIPTOR_SET,
+                tonic_reflection::pb::v1::FILE_DESCRIPTOR_SET,
+            ])?)
+            .layer(tower::timeout::TimeoutLayer::new(
+                std::time::Duration::from_secs(30),
+            ))
+            .layer(
+                TraceLayer::new_for_grpc()
+                    .make_span_with(make_span)
+                    .on_request(accept_trace),
+            )
+            .layer(AuthLayer::new(dk))
+            .layer(authz),
+    );
+    let (reporter, health) = tonic_health::server::health_reporter();
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .build_v1()?;
+    let server = server.add_service(health).add_service(reflection);
+
+    // keep the suppression list in postgres if configured, otherwise in memory
+    let router = match config.server.db_url.clone() {
+        Some(url) => {
+            info!("Using Postgres as suppression list storage");
+            let repo = PostgresRepo::new(&url).await?;
+            report_ping(reporter, vec![SERVICE_NAME, ""], repo.clone());
+            server.add_service(NotificationService::new(repo, config).into_server())
+        }
+        None => {
+            info!("Using memory as suppression list storage");
+            report_health(reporter, vec![SERVICE_NAME, ""], || async { Ok(()) });
+            server.add_service(NotificationService::new(MemoryRepo::new(), config).into_server())
+        }
+    };
+
+    router.serve(addr).await?;
     Ok(())
 }
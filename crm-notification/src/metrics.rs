@@ -0,0 +1,38 @@
+use std::sync::LazyLock;
+
+use prometheus::{register_int_counter_vec, register_int_gauge, IntCounterVec, IntGauge};
+
+use crate::pb::Channel;
+
+static MESSAGES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "notification_messages_total",
+        "Messages handed to the senders by channel and result, sent or failed",
+        &["channel", "result"]
+    )
+    .expect("metric is registered once")
+});
+
+static QUEUE_DEPTH: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "notification_queue_depth",
+        "Messages waiting in the queue of the sender"
+    )
+    .expect("metric is registered once")
+});
+
+/// Record a message sent, or failed to be sent, on the channel
+pub(crate) fn record_message(channel: Channel, sent: bool) {
+    let channel = match channel {
+        Channel::Unspecified => "unspecified",
+        Channel::Email => "email",
+        Channel::Sms => "sms",
+        Channel::InApp => "in_app",
+    };
+    let result = if sent { "sent" } else { "failed" };
+    MESSAGES.with_label_values(&[channel, result]).inc();
+}
+
+pub(crate) fn record_queue_depth(depth: usize) {
+    QUEUE_DEPTH.set(depth as i64);
+}
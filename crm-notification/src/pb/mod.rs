@@ -1,3 +1,6 @@
 mod notification;
 
 pub use notification::*;
+
+/// Encoded descriptors of the protos, served by the reflection service
+pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!("notification_descriptor.bin");
@@ -14,9 +14,61 @@ pub struct EmailMessage {
     /// recipients of the email
     #[prost(string, repeated, tag = "4")]
     pub recipients: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
-    /// body of the email
+    /// plain text body of the email, used as fallback when `html` is set
     #[prost(string, tag = "5")]
     pub body: ::prost::alloc::string::String,
+    /// html body of the email
+    #[prost(string, tag = "6")]
+    pub html: ::prost::alloc::string::String,
+    /// carbon copy recipients of the email
+    #[prost(string, repeated, tag = "7")]
+    pub cc: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// blind carbon copy recipients of the email
+    #[prost(string, repeated, tag = "8")]
+    pub bcc: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// address replies should be sent to
+    #[prost(string, tag = "9")]
+    pub reply_to: ::prost::alloc::string::String,
+    /// custom headers of the email, e.g. List-Unsubscribe
+    #[prost(map = "string, string", tag = "10")]
+    pub headers: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
+    /// files attached to the email, or embedded in the html if inline
+    #[prost(message, repeated, tag = "11")]
+    pub attachments: ::prost::alloc::vec::Vec<Attachment>,
+}
+/// file attached to an email
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Attachment {
+    /// file name of the attachment
+    #[prost(string, tag = "1")]
+    pub filename: ::prost::alloc::string::String,
+    /// mime type of the attachment, e.g. image/png
+    /// taken from the response when fetched from `url` if empty
+    #[prost(string, tag = "2")]
+    pub content_type: ::prost::alloc::string::String,
+    /// content id to reference an inline attachment from html as `cid:<content_id>`,
+    /// the attachment is a regular one if empty
+    #[prost(string, tag = "5")]
+    pub content_id: ::prost::alloc::string::String,
+    /// content of the attachment
+    #[prost(oneof = "attachment::Source", tags = "3, 4")]
+    pub source: ::core::option::Option<attachment::Source>,
+}
+/// Nested message and enum types in `Attachment`.
+pub mod attachment {
+    /// content of the attachment
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Source {
+        /// raw content
+        #[prost(bytes, tag = "3")]
+        Data(::prost::alloc::vec::Vec<u8>),
+        /// url to fetch the content from when the email is delivered
+        #[prost(string, tag = "4")]
+        Url(::prost::alloc::string::String),
+    }
 }
 /// sms message to be sent
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -53,6 +105,12 @@ pub struct InAppMessage {
 /// request to send a message
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SendRequest {
+    /// when to send the message, sent immediately if not set
+    #[prost(message, optional, tag = "5")]
+    pub send_at: ::core::option::Option<::prost_types::Timestamp>,
+    /// how long after `send_at` the message may still be sent, never expires if not set
+    #[prost(message, optional, tag = "6")]
+    pub ttl: ::core::option::Option<::prost_types::Duration>,
     /// one of the message types to send
     #[prost(oneof = "send_request::Msg", tags = "2, 3, 4")]
     pub msg: ::core::option::Option<send_request::Msg>,
@@ -76,9 +134,186 @@ pub struct SendResponse {
     /// unique identifier of the message
     #[prost(string, tag = "1")]
     pub message_id: ::prost::alloc::string::String,
-    /// timestamp of when the message was sent
+    /// timestamp of when the message was sent, or is scheduled to be sent
     #[prost(message, optional, tag = "2")]
     pub timestamp: ::core::option::Option<::prost_types::Timestamp>,
+    /// status of the message
+    #[prost(enumeration = "SendStatus", tag = "3")]
+    pub status: i32,
+    /// recipients filtered out by the suppression list
+    #[prost(string, repeated, tag = "4")]
+    pub suppressed: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// a recipient that must not receive notifications on a channel
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Suppression {
+    /// channel the suppression applies to
+    #[prost(enumeration = "Channel", tag = "1")]
+    pub channel: i32,
+    /// email address, phone number or device id, depending on the channel
+    #[prost(string, tag = "2")]
+    pub recipient: ::prost::alloc::string::String,
+    /// reason of the suppression
+    #[prost(enumeration = "SuppressionReason", tag = "3")]
+    pub reason: i32,
+    /// timestamp of when the suppression was added
+    #[prost(message, optional, tag = "4")]
+    pub created_at: ::core::option::Option<::prost_types::Timestamp>,
+}
+/// request to add a recipient to the suppression list
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AddSuppressionRequest {
+    #[prost(enumeration = "Channel", tag = "1")]
+    pub channel: i32,
+    #[prost(string, tag = "2")]
+    pub recipient: ::prost::alloc::string::String,
+    #[prost(enumeration = "SuppressionReason", tag = "3")]
+    pub reason: i32,
+}
+/// request to remove a recipient from the suppression list
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RemoveSuppressionRequest {
+    #[prost(enumeration = "Channel", tag = "1")]
+    pub channel: i32,
+    #[prost(string, tag = "2")]
+    pub recipient: ::prost::alloc::string::String,
+}
+/// response to a remove suppression request
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct RemoveSuppressionResponse {
+    /// whether the recipient was on the suppression list
+    #[prost(bool, tag = "1")]
+    pub removed: bool,
+}
+/// request to list the suppression list
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ListSuppressionsRequest {
+    /// channel to list, all channels if unspecified
+    #[prost(enumeration = "Channel", tag = "1")]
+    pub channel: i32,
+}
+/// request to cancel a scheduled message
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CancelRequest {
+    /// unique identifier of the scheduled message
+    #[prost(string, tag = "1")]
+    pub message_id: ::prost::alloc::string::String,
+}
+/// response to a cancel request
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct CancelResponse {
+    /// whether the message was still scheduled
+    #[prost(bool, tag = "1")]
+    pub cancelled: bool,
+}
+/// status of a sent message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum SendStatus {
+    Unspecified = 0,
+    /// message was dispatched to at least one recipient
+    Sent = 1,
+    /// all recipients are suppressed, message was not dispatched
+    Suppressed = 2,
+    /// message is held until `send_at`
+    Scheduled = 3,
+    /// `ttl` elapsed before the message could be sent
+    Expired = 4,
+}
+impl SendStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            SendStatus::Unspecified => "SEND_STATUS_UNSPECIFIED",
+            SendStatus::Sent => "SEND_STATUS_SENT",
+            SendStatus::Suppressed => "SEND_STATUS_SUPPRESSED",
+            SendStatus::Scheduled => "SEND_STATUS_SCHEDULED",
+            SendStatus::Expired => "SEND_STATUS_EXPIRED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "SEND_STATUS_UNSPECIFIED" => Some(Self::Unspecified),
+            "SEND_STATUS_SENT" => Some(Self::Sent),
+            "SEND_STATUS_SUPPRESSED" => Some(Self::Suppressed),
+            "SEND_STATUS_SCHEDULED" => Some(Self::Scheduled),
+            "SEND_STATUS_EXPIRED" => Some(Self::Expired),
+            _ => None,
+        }
+    }
+}
+/// channel a notification is delivered through
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Channel {
+    Unspecified = 0,
+    Email = 1,
+    Sms = 2,
+    InApp = 3,
+}
+impl Channel {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Channel::Unspecified => "CHANNEL_UNSPECIFIED",
+            Channel::Email => "CHANNEL_EMAIL",
+            Channel::Sms => "CHANNEL_SMS",
+            Channel::InApp => "CHANNEL_IN_APP",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "CHANNEL_UNSPECIFIED" => Some(Self::Unspecified),
+            "CHANNEL_EMAIL" => Some(Self::Email),
+            "CHANNEL_SMS" => Some(Self::Sms),
+            "CHANNEL_IN_APP" => Some(Self::InApp),
+            _ => None,
+        }
+    }
+}
+/// reason why a recipient is suppressed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum SuppressionReason {
+    Unspecified = 0,
+    Unsubscribed = 1,
+    HardBounce = 2,
+    Complaint = 3,
+    Manual = 4,
+}
+impl SuppressionReason {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            SuppressionReason::Unspecified => "SUPPRESSION_REASON_UNSPECIFIED",
+            SuppressionReason::Unsubscribed => "SUPPRESSION_REASON_UNSUBSCRIBED",
+            SuppressionReason::HardBounce => "SUPPRESSION_REASON_HARD_BOUNCE",
+            SuppressionReason::Complaint => "SUPPRESSION_REASON_COMPLAINT",
+            SuppressionReason::Manual => "SUPPRESSION_REASON_MANUAL",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "SUPPRESSION_REASON_UNSPECIFIED" => Some(Self::Unspecified),
+            "SUPPRESSION_REASON_UNSUBSCRIBED" => Some(Self::Unsubscribed),
+            "SUPPRESSION_REASON_HARD_BOUNCE" => Some(Self::HardBounce),
+            "SUPPRESSION_REASON_COMPLAINT" => Some(Self::Complaint),
+            "SUPPRESSION_REASON_MANUAL" => Some(Self::Manual),
+            _ => None,
+        }
+    }
 }
 /// Generated client implementations.
 pub mod notification_client {
@@ -192,6 +427,108 @@ pub mod notification_client {
                 .insert(GrpcMethod::new("notification.Notification", "Send"));
             self.inner.streaming(req, path, codec).await
         }
+        /// Cancel a scheduled notification which is not sent yet.
+        pub async fn cancel(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CancelRequest>,
+        ) -> std::result::Result<tonic::Response<super::CancelResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/notification.Notification/Cancel",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("notification.Notification", "Cancel"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Add a recipient to the suppression list of a channel.
+        pub async fn add_suppression(
+            &mut self,
+            request: impl tonic::IntoRequest<super::AddSuppressionRequest>,
+        ) -> std::result::Result<tonic::Response<super::Suppression>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/notification.Notification/AddSuppression",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("notification.Notification", "AddSuppression"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Remove a recipient from the suppression list of a channel.
+        pub async fn remove_suppression(
+            &mut self,
+            request: impl tonic::IntoRequest<super::RemoveSuppressionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::RemoveSuppressionResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/notification.Notification/RemoveSuppression",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("notification.Notification", "RemoveSuppression"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        /// List the suppressed recipients.
+        pub async fn list_suppressions(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListSuppressionsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::Suppression>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/notification.Notification/ListSuppressions",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("notification.Notification", "ListSuppressions"),
+                );
+            self.inner.server_streaming(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -212,6 +549,38 @@ pub mod notification_server {
             &self,
             request: tonic::Request<tonic::Streaming<super::SendRequest>>,
         ) -> std::result::Result<tonic::Response<Self::SendStream>, tonic::Status>;
+        /// Cancel a scheduled notification which is not sent yet.
+        async fn cancel(
+            &self,
+            request: tonic::Request<super::CancelRequest>,
+        ) -> std::result::Result<tonic::Response<super::CancelResponse>, tonic::Status>;
+        /// Add a recipient to the suppression list of a channel.
+        async fn add_suppression(
+            &self,
+            request: tonic::Request<super::AddSuppressionRequest>,
+        ) -> std::result::Result<tonic::Response<super::Suppression>, tonic::Status>;
+        /// Remove a recipient from the suppression list of a channel.
+        async fn remove_suppression(
+            &self,
+            request: tonic::Request<super::RemoveSuppressionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::RemoveSuppressionResponse>,
+            tonic::Status,
+        >;
+        /// Server streaming response type for the ListSuppressions method.
+        type ListSuppressionsStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::Suppression, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        /// List the suppressed recipients.
+        async fn list_suppressions(
+            &self,
+            request: tonic::Request<super::ListSuppressionsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<Self::ListSuppressionsStream>,
+            tonic::Status,
+        >;
     }
     /// The Notification service provides a way to send notifications to users.
     #[derive(Debug)]
@@ -336,6 +705,190 @@ pub mod notification_server {
                     };
                     Box::pin(fut)
                 }
+                "/notification.Notification/Cancel" => {
+                    #[allow(non_camel_case_types)]
+                    struct CancelSvc<T: Notification>(pub Arc<T>);
+                    impl<
+                        T: Notification,
+                    > tonic::server::UnaryService<super::CancelRequest>
+                    for CancelSvc<T> {
+                        type Response = super::CancelResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CancelRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Notification>::cancel(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = CancelSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/notification.Notification/AddSuppression" => {
+                    #[allow(non_camel_case_types)]
+                    struct AddSuppressionSvc<T: Notification>(pub Arc<T>);
+                    impl<
+                        T: Notification,
+                    > tonic::server::UnaryService<super::AddSuppressionRequest>
+                    for AddSuppressionSvc<T> {
+                        type Response = super::Suppression;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::AddSuppressionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Notification>::add_suppression(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = AddSuppressionSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/notification.Notification/RemoveSuppression" => {
+                    #[allow(non_camel_case_types)]
+                    struct RemoveSuppressionSvc<T: Notification>(pub Arc<T>);
+                    impl<
+                        T: Notification,
+                    > tonic::server::UnaryService<super::RemoveSuppressionRequest>
+                    for RemoveSuppressionSvc<T> {
+                        type Response = super::RemoveSuppressionResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::RemoveSuppressionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Notification>::remove_suppression(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = RemoveSuppressionSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/notification.Notification/ListSuppressions" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListSuppressionsSvc<T: Notification>(pub Arc<T>);
+                    impl<
+                        T: Notification,
+                    > tonic::server::ServerStreamingService<
+                        super::ListSuppressionsRequest,
+                    > for ListSuppressionsSvc<T> {
+                        type Response = super::Suppression;
+                        type ResponseStream = T::ListSuppressionsStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListSuppressionsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Notification>::list_suppressions(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ListSuppressionsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
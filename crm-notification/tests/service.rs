@@ -1,17 +1,21 @@
-use std::{net::SocketAddr, time::Duration};
+use std::net::SocketAddr;
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use crm_core::ConfigExt;
 use crm_notification::{
     pb::{
-        notification_client::NotificationClient, EmailMessage, InAppMessage, SendRequest,
-        SmsMessage,
+        notification_client::NotificationClient, AddSuppressionRequest, CancelRequest, Channel,
+        EmailMessage, InAppMessage, ListSuppressionsRequest, RemoveSuppressionRequest, SendRequest,
+        SendStatus, SmsMessage, SuppressionReason,
     },
-    AppConfig, NotificationService,
+    AppConfig, MemoryRepo, NotificationService,
 };
 use futures::StreamExt;
-use tokio::time::sleep;
-use tonic::{transport::Server, Request};
+use prost_types::Timestamp;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{transport::Server, Code, Request};
 
 #[tokio::test]
 async fn send_notification_should_work() {
@@ -22,15 +26,9 @@ async fn send_notification_should_work() {
         .await
         .unwrap();
     let stream = tokio_stream::iter(vec![
-        SendRequest {
-            msg: Some(EmailMessage::fake().into()),
-        },
-        SendRequest {
-            msg: Some(SmsMessage::fake().into()),
-        },
-        SendRequest {
-            msg: Some(InAppMessage::fake().into()),
-        },
+        SendRequest::from(EmailMessage::fake()),
+        SendRequest::from(SmsMessage::fake()),
+        SendRequest::from(InAppMessage::fake()),
     ]);
     let request = Request::new(stream);
     let response = client.send(request).await.unwrap().into_inner();
@@ -42,19 +40,202 @@ async fn send_notification_should_work() {
     assert_eq!(ret.len(), 3);
 }
 
+#[tokio::test]
+async fn suppressed_recipients_should_be_filtered() -> Result<()> {
+    let addr = start_server().await?;
+    let mut client = NotificationClient::connect(format!("http://{}", addr)).await?;
+
+    let mut email = EmailMessage::fake();
+    let unsubscribed = email.recipients[0].clone();
+    email.recipients.push("kept@example.com".to_string());
+    client
+        .add_suppression(AddSuppressionRequest {
+            channel: Channel::Email as _,
+            recipient: unsubscribed.to_uppercase(),
+            reason: SuppressionReason::Unsubscribed as _,
+        })
+        .await?;
+
+    let in_app = InAppMessage::fake();
+    client
+        .add_suppression(AddSuppressionRequest {
+            channel: Channel::InApp as _,
+            recipient: in_app.device_id.clone(),
+            reason: SuppressionReason::Manual as _,
+        })
+        .await?;
+
+    let suppressions: Vec<_> = client
+        .list_suppressions(ListSuppressionsRequest {
+            channel: Channel::Email as _,
+        })
+        .await?
+        .into_inner()
+        .filter_map(|res| async { res.ok() })
+        .collect()
+        .await;
+    assert_eq!(suppressions.len(), 1);
+    assert_eq!(suppressions[0].recipient, unsubscribed.to_lowercase());
+
+    let stream = tokio_stream::iter(vec![SendRequest::from(email), SendRequest::from(in_app)]);
+    let ret: Vec<_> = client
+        .send(Request::new(stream))
+        .await?
+        .into_inner()
+        .filter_map(|res| async { res.ok() })
+        .collect()
+        .await;
+
+    assert_eq!(ret.len(), 2);
+    assert_eq!(ret[0].status(), SendStatus::Sent);
+    assert_eq!(ret[0].suppressed, vec![unsubscribed.to_lowercase()]);
+    assert_eq!(ret[1].status(), SendStatus::Suppressed);
+
+    let removed = client
+        .remove_suppression(RemoveSuppressionRequest {
+            channel: Channel::Email as _,
+            recipient: unsubscribed,
+        })
+        .await?
+        .into_inner()
+        .removed;
+    assert!(removed);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn scheduled_notification_should_be_cancellable() -> Result<()> {
+    let addr = start_server().await?;
+    let mut client = NotificationClient::connect(format!("http://{}", addr)).await?;
+
+    let now = Utc::now();
+    let email = EmailMessage::fake();
+    let scheduled = SendRequest {
+        send_at: Some(to_ts(now + chrono::Duration::hours(1))),
+        ..SendRequest::from(email.clone())
+    };
+    let expired = SendRequest {
+        send_at: Some(to_ts(now - chrono::Duration::hours(1))),
+        ttl: Some(prost_types::Duration {
+            seconds: 60,
+            nanos: 0,
+        }),
+        ..SendRequest::from(SmsMessage::fake())
+    };
+
+    let stream = tokio_stream::iter(vec![scheduled, expired]);
+    let ret: Vec<_> = client
+        .send(Request::new(stream))
+        .await?
+        .into_inner()
+        .filter_map(|res| async { res.ok() })
+        .collect()
+        .await;
+
+    assert_eq!(ret.len(), 2);
+    assert_eq!(ret[0].status(), SendStatus::Scheduled);
+    assert_eq!(
+        ret[0].timestamp.unwrap().seconds,
+        (now + chrono::Duration::hours(1)).timestamp()
+    );
+    assert_eq!(ret[1].status(), SendStatus::Expired);
+
+    let cancel = CancelRequest {
+        message_id: ret[0].message_id.clone(),
+    };
+    assert!(client.cancel(cancel.clone()).await?.into_inner().cancelled);
+    assert!(!client.cancel(cancel).await?.into_inner().cancelled);
+
+    // the id of the cancelled message is released, it can be sent again
+    let stream = tokio_stream::iter(vec![SendRequest::from(email)]);
+    let ret: Vec<_> = client
+        .send(Request::new(stream))
+        .await?
+        .into_inner()
+        .filter_map(|res| async { res.ok() })
+        .collect()
+        .await;
+    assert_eq!(ret[0].status(), SendStatus::Sent);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn duplicated_message_should_return_original_response() -> Result<()> {
+    let addr = start_server().await?;
+    let mut client = NotificationClient::connect(format!("http://{}", addr)).await?;
+
+    let email = EmailMessage::fake();
+    let stream = tokio_stream::iter(vec![
+        SendRequest::from(email.clone()),
+        SendRequest::from(email.clone()),
+    ]);
+    let ret: Vec<_> = client
+        .send(Request::new(stream))
+        .await?
+        .into_inner()
+        .filter_map(|res| async { res.ok() })
+        .collect()
+        .await;
+    assert_eq!(ret.len(), 2);
+    assert_eq!(ret[0], ret[1]);
+
+    // retried stream
+    let stream = tokio_stream::iter(vec![SendRequest::from(email)]);
+    let retried: Vec<_> = client
+        .send(Request::new(stream))
+        .await?
+        .into_inner()
+        .filter_map(|res| async { res.ok() })
+        .collect()
+        .await;
+    assert_eq!(retried, ret[..1]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalid_email_should_be_rejected() -> Result<()> {
+    let addr = start_server().await?;
+    let mut client = NotificationClient::connect(format!("http://{}", addr)).await?;
+
+    let mut email = EmailMessage::fake();
+    email.recipients = vec!["not an address".to_string()];
+    let stream = tokio_stream::iter(vec![SendRequest::from(email)]);
+    let ret: Vec<_> = client
+        .send(Request::new(stream))
+        .await?
+        .into_inner()
+        .collect()
+        .await;
+    assert_eq!(ret.len(), 1);
+    assert_eq!(ret[0].as_ref().unwrap_err().code(), Code::InvalidArgument);
+
+    Ok(())
+}
+
+fn to_ts(dt: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: dt.timestamp(),
+        nanos: dt.timestamp_subsec_nanos() as i32,
+    }
+}
+
 async fn start_server() -> Result<SocketAddr> {
     let config = AppConfig::load()?;
-    let addr = format!("127.0.0.1:{}", config.server.port).parse()?;
+    // the connections are accepted once the server runs, the listener is bound before
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
 
-    let svc = NotificationService::new(config).into_server();
+    let svc = NotificationService::new(MemoryRepo::new(), config).into_server();
     tokio::spawn(async move {
         Server::builder()
             .add_service(svc)
-            .serve(addr)
+            .serve_with_incoming(TcpListenerStream::new(listener))
             .await
             .unwrap();
     });
-    sleep(Duration::from_micros(1)).await;
 
     Ok(addr)
 }
@@ -0,0 +1,125 @@
+use std::{net::SocketAddr, time::Duration};
+
+use anyhow::Result;
+use crm_core::{accept_trace, make_span, ConfigExt, SendTrace};
+use crm_notification::{
+    pb::{notification_client::NotificationClient, EmailMessage, SendRequest, SmsMessage},
+    AppConfig, MemoryRepo, NotificationService,
+};
+use futures::StreamExt;
+use opentelemetry::{global, trace::TracerProvider as _};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    testing::trace::InMemorySpanExporter,
+    trace::{Tracer, TracerProvider},
+};
+use tokio::{net::TcpListener, time::sleep};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{
+    transport::{Endpoint, Server},
+    Request,
+};
+use tower_http::trace::TraceLayer;
+use tracing::{info_span, Instrument};
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt};
+
+#[tokio::test]
+async fn send_should_be_traced_under_the_span_of_the_caller() -> Result<()> {
+    let exporter = InMemorySpanExporter::default();
+    let provider = TracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let _guard = tracing::subscriber::set_default(subscriber(provider.tracer("test")));
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let addr = start_server().await?;
+    let channel = Endpoint::from_shared(format!("http://{addr}"))?
+        .connect()
+        .await?;
+    let mut client = NotificationClient::with_interceptor(channel, SendTrace);
+
+    async {
+        let stream = tokio_stream::iter(vec![
+            SendRequest::from(EmailMessage::fake()),
+            SendRequest::from(SmsMessage::fake()),
+        ]);
+        let ret: Vec<_> = client
+            .send(Request::new(stream))
+            .await?
+            .into_inner()
+            .collect()
+            .await;
+        assert_eq!(ret.len(), 2);
+        Ok::<_, anyhow::Error>(())
+    }
+    .instrument(info_span!("caller"))
+    .await?;
+
+    // the spans of the server end with the response stream
+    sleep(Duration::from_millis(100)).await;
+    let spans = exporter.get_finished_spans()?;
+    let span = |name: &str| {
+        spans
+            .iter()
+            .find(|s| s.name == name)
+            .unwrap_or_else(|| panic!("span {name} is exported"))
+    };
+    let parent_of = |name: &str, parent: &str| {
+        assert_eq!(
+            span(name).parent_span_id,
+            span(parent).span_context.span_id(),
+            "{parent} is the parent of {name}"
+        );
+    };
+
+    let caller = span("caller");
+    let send = span("send");
+    let dispatches: Vec<_> = spans.iter().filter(|s| s.name == "dispatch").collect();
+    assert_eq!(dispatches.len(), 2);
+    for dispatch in dispatches {
+        assert_eq!(
+            dispatch.span_context.trace_id(),
+            caller.span_context.trace_id()
+        );
+        // under the span of the sender of the channel
+        let sender = spans
+            .iter()
+            .find(|s| s.span_context.span_id() == dispatch.parent_span_id)
+            .unwrap();
+        assert_eq!(sender.parent_span_id, send.span_context.span_id());
+    }
+    parent_of("send", "send-handler");
+    parent_of("send-handler", "incoming request");
+    parent_of("incoming request", "caller");
+
+    Ok(())
+}
+
+fn subscriber(tracer: Tracer) -> impl tracing::Subscriber + Send + Sync {
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+async fn start_server() -> Result<SocketAddr> {
+    let config = AppConfig::load()?;
+    // the connections are accepted once the server runs, the listener is bound before
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let svc = NotificationService::new(MemoryRepo::new(), config).into_server();
+    tokio::spawn(async move {
+        Server::builder()
+            .layer(
+                TraceLayer::new_for_grpc()
+                    .make_span_with(make_span)
+                    .on_request(accept_trace),
+            )
+            .add_service(svc)
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+
+    Ok(addr)
+}
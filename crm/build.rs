@@ -6,6 +6,7 @@ fn main() -> Result<()> {
     let builder = tonic_build::configure();
     builder
         .out_dir("src/pb")
+        .file_descriptor_set_path("src/pb/crm_descriptor.bin")
         .type_attribute("crm.WelcomeRequest", "#[derive(derive_builder::Builder)]")
         .type_attribute("crm.RecallRequest", "#[derive(derive_builder::Builder)]")
         .type_attribute("crm.RemindRequest", "#[derive(derive_builder::Builder)]")
@@ -13,9 +14,14 @@ fn main() -> Result<()> {
             "crm.WelcomeRequest.content_ids",
             r#"#[builder(setter(each(name="content_id", into)))]"#,
         )
+        .field_attribute("crm.WelcomeRequest.strict", "#[builder(default)]")
         .compile(
-            &["../protos/crm/message.proto", "../protos/crm/rpc.proto"],
-            &["../protos/crm"],
+            &[
+                "../protos/crm/message.proto",
+                "../protos/crm/rpc.proto",
+                "../protos/crm.proto",
+            ],
+            &["../protos/crm", "../protos"],
         )?;
     Ok(())
 }
@@ -0,0 +1,146 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crm_core::auth::{set_bearer_token, EncodingKey, TokenOptions, User};
+use tonic::{Request, Status};
+
+use crate::CrmService;
+
+const SERVICE_NAME: &str = "crm";
+/// The scopes of the service tokens are granted to this role by the policies of
+/// the downstream services
+const SERVICE_ROLE: &str = "service";
+/// Lifetime of the service tokens
+const SERVICE_TOKEN_EXPIRY: Duration = Duration::from_secs(5 * 60);
+/// A service token is renewed once it expires within this margin
+const SERVICE_TOKEN_RENEWAL: Duration = Duration::from_secs(60);
+
+/// Why no token can be attached to the downstream calls
+#[derive(Debug)]
+pub enum TokenError {
+    /// the service token cannot be signed
+    Sign(String),
+    /// the token is not a valid metadata value
+    Invalid,
+}
+
+/// Signs the short-lived service tokens of the downstream calls, a token is
+/// reused until it nears its expiry
+pub struct ServiceTokens {
+    key: EncodingKey,
+    user: User,
+    /// the current token and its expiry
+    current: Mutex<Option<(String, Instant)>>,
+}
+
+impl From<TokenError> for Status {
+    fn from(e: TokenError) -> Self {
+        match e {
+            TokenError::Sign(e) => Status::internal(e),
+            TokenError::Invalid => Status::internal("invalid token"),
+        }
+    }
+}
+
+impl ServiceTokens {
+    pub fn new(key: EncodingKey, email: String) -> Self {
+        let user = User {
+            name: SERVICE_NAME.to_string(),
+            email,
+            roles: vec![SERVICE_ROLE.to_string()],
+            scopes: vec![],
+        };
+        Self {
+            key,
+            user,
+            current: Mutex::new(None),
+        }
+    }
+
+    /// The current token, or a new one if it expires soon
+    pub fn get(&self) -> Result<String, TokenError> {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if let Some((token, expires_at)) = current.as_ref() {
+            if *expires_at > now + SERVICE_TOKEN_RENEWAL {
+                return Ok(token.clone());
+            }
+        }
+
+        let opts = TokenOptions {
+            expiry: SERVICE_TOKEN_EXPIRY.as_secs(),
+            ..Default::default()
+        };
+        let token = self
+            .key
+            .issue(self.user.clone(), &opts)
+            .map_err(|e| TokenError::Sign(e.to_string()))?;
+        *current = Some((token.clone(), now + SERVICE_TOKEN_EXPIRY));
+        Ok(token)
+    }
+}
+
+impl CrmService {
+    /// The token the downstream services are called with
+    pub fn downstream_token(&self) -> Result<String, TokenError> {
+        self.service_tokens.get()
+    }
+}
+
+/// A downstream request carrying the given token
+pub(crate) fn authorized<T>(message: T, token: &str) -> Result<Request<T>, TokenError> {
+    let mut req = Request::new(message);
+    set_bearer_token(&mut req, token).map_err(|_| TokenError::Invalid)?;
+    Ok(req)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppConfig;
+    use crm_core::{
+        auth::{bearer_token, DecodingKey},
+        ConfigExt,
+    };
+
+    #[test]
+    fn service_tokens_should_be_short_lived_and_reused() {
+        let key = EncodingKey::generate();
+        let dk = DecodingKey::load(&key.public_key().to_pem()).unwrap();
+        let tokens = ServiceTokens::new(key, "crm@example.com".to_string());
+
+        let token = tokens.get().unwrap();
+        let user = dk.verify(&token).unwrap();
+        assert_eq!(user.name, SERVICE_NAME);
+        assert_eq!(user.roles, vec![SERVICE_ROLE]);
+        let (_, expires_at) = tokens.current.lock().unwrap().clone().unwrap();
+        assert!(expires_at <= Instant::now() + SERVICE_TOKEN_EXPIRY);
+        assert_eq!(tokens.get().unwrap(), token);
+
+        // renewed once it nears its expiry
+        *tokens.current.lock().unwrap() = Some((token.clone(), Instant::now()));
+        assert_ne!(tokens.get().unwrap(), token);
+
+        let req = authorized((), &token).unwrap();
+        assert_eq!(bearer_token(&req), Some(token.as_str()));
+    }
+
+    #[tokio::test]
+    async fn crm_should_not_start_without_service_key() {
+        let mut config = AppConfig::load().unwrap();
+        config.auth.sk = None;
+        assert!(CrmService::try_new(config).await.is_err());
+
+        let key = EncodingKey::generate();
+        let sk = std::env::temp_dir().join(format!("crm-sk-{}.pem", std::process::id()));
+        std::fs::write(&sk, key.to_pem()).unwrap();
+        let mut config = AppConfig::load().unwrap();
+        config.auth.sk = Some(sk);
+        let svc = CrmService::try_new(config).await.unwrap();
+        let token = svc.downstream_token().unwrap();
+        let dk = DecodingKey::load(&key.public_key().to_pem()).unwrap();
+        assert_eq!(dk.verify(&token).unwrap().name, SERVICE_NAME);
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use crm_metadata::pb::{Content, MaterializeErrorKind, MaterializeRequest, MaterializeResponse};
+use futures::StreamExt;
+use tonic::Status;
+use tracing::{debug, warn};
+
+use super::auth::authorized;
+use crate::CrmService;
+
+/// attempts to materialize the contents of a run, only the contents failed in an
+/// attempt are requested again
+const MATERIALIZE_ATTEMPTS: usize = 2;
+
+/// Contents materialized during a campaign run, each content is materialized at
+/// most once however many times it is requested in the run
+#[derive(Debug, Default)]
+pub(crate) struct RunContents {
+    /// materialized contents, none if not found
+    contents: HashMap<u32, Option<Content>>,
+    /// reasons the contents are not materialized yet, not cached as they may
+    /// succeed later in the run
+    failures: HashMap<u32, String>,
+}
+
+/// Contents of a campaign in the requested order
+#[derive(Debug, Default)]
+pub(crate) struct Materialized {
+    pub(crate) contents: Vec<Content>,
+    /// reasons the missing contents are not materialized
+    pub(crate) failures: Vec<String>,
+}
+
+impl RunContents {
+    /// The contents of the given ids, only the ones not materialized yet in this
+    /// run are requested from the metadata service
+    pub(crate) async fn get(
+        &mut self,
+        svc: &CrmService,
+        token: &str,
+        ids: &[u32],
+    ) -> Result<Materialized, Status> {
+        for _ in 0..MATERIALIZE_ATTEMPTS {
+            let missed = self.missed(ids);
+            debug!(
+                "{} contents cached in run, {} missed",
+                ids.len() - missed.len(),
+                missed.len()
+            );
+            if missed.is_empty() {
+                break;
+            }
+
+            let mut responses = svc
+                .metadata_pool
+                .get()
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?
+                .materialize(authorized(
+                    MaterializeRequest::new_with_ids(&missed),
+                    token,
+                )?)
+                .await?
+                .into_inner();
+            while let Some(response) = responses.next().await {
+                self.insert(response?);
+            }
+        }
+
+        let ret = self.collect(ids);
+        if !ret.failures.is_empty() {
+            warn!("Failed to materialize contents: {:?}", ret.failures);
+        }
+        Ok(ret)
+    }
+
+    /// The ids not materialized yet in this run
+    fn missed(&self, ids: &[u32]) -> Vec<u32> {
+        ids.iter()
+            .filter(|id| !self.contents.contains_key(id))
+            .copied()
+            .collect()
+    }
+
+    fn insert(&mut self, response: MaterializeResponse) {
+        let id = response.id;
+        match response.into_result() {
+            Ok(content) => {
+                self.failures.remove(&id);
+                self.contents.insert(id, Some(content));
+            }
+            Err(e) if e.kind() == MaterializeErrorKind::NotFound => {
+                self.failures.remove(&id);
+                self.contents.insert(id, None);
+            }
+            Err(e) => {
+                self.failures.insert(id, e.message);
+            }
+        }
+    }
+
+    fn collect(&self, ids: &[u32]) -> Materialized {
+        let mut ret = Materialized::default();
+        for id in ids {
+            match self.contents.get(id) {
+                Some(Some(content)) => ret.contents.push(content.clone()),
+                Some(None) => ret.failures.push(format!("Content {id} not found")),
+                None => ret.failures.push(
+                    self.failures
+                        .get(id)
+                        .cloned()
+                        .unwrap_or_else(|| format!("Content {id} is not materialized")),
+                ),
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(id: u32) -> Content {
+        Content {
+            id,
+            name: format!("content {id}"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn contents_materialized_in_run_should_not_be_requested_again() {
+        let mut run = RunContents::default();
+        assert_eq!(run.missed(&[1, 2, 3]), vec![1, 2, 3]);
+
+        run.insert(MaterializeResponse::found(content(1)));
+        run.insert(MaterializeResponse::not_found(2));
+        run.insert(MaterializeResponse::failed(3, "timeout".to_string()));
+        // the content not found is cached, the failed one is requested again
+        assert_eq!(run.missed(&[1, 2, 3]), vec![3]);
+
+        let Materialized { contents, failures } = run.collect(&[3, 2, 1]);
+        assert_eq!(contents, vec![content(1)]);
+        assert_eq!(failures, vec!["timeout", "Content 2 not found"]);
+
+        run.insert(MaterializeResponse::found(content(3)));
+        assert!(run.missed(&[1, 2, 3]).is_empty());
+        let Materialized { contents, failures } = run.collect(&[3, 1]);
+        assert_eq!(contents, vec![content(3), content(1)]);
+        assert!(failures.is_empty());
+    }
+}
@@ -1,18 +1,40 @@
+mod auth;
+mod contents;
+mod user;
+
 use crate::{
     pb::{WelcomeRequest, WelcomeResponse},
     CrmService,
 };
+use auth::authorized;
+pub(crate) use auth::ServiceTokens;
 use chrono::{Duration, Utc};
-use crm_metadata::pb::{Content, MaterializeRequest};
-use crm_notification::pb::SendRequest;
+use contents::{Materialized, RunContents};
+use crm_metadata::pb::Content;
+use crm_notification::pb::{attachment::Source, Attachment, EmailMessage, SendRequest};
 use futures::StreamExt;
 use tonic::{Response, Status};
-use tracing::debug;
+use tracing::{debug, Instrument, Span};
 use user_stat::pb::QueryRequest;
+use uuid::Uuid;
 
 impl CrmService {
-    pub async fn welcome(&self, req: WelcomeRequest) -> Result<Response<WelcomeResponse>, Status> {
+    pub async fn welcome(
+        &self,
+        req: WelcomeRequest,
+        token: &str,
+    ) -> Result<Response<WelcomeResponse>, Status> {
         let request_id = req.id;
+        let mut run_contents = RunContents::default();
+        let Materialized { contents, failures } =
+            run_contents.get(self, token, &req.content_ids).await?;
+        if req.strict && !failures.is_empty() {
+            return Err(Status::failed_precondition(format!(
+                "Failed to materialize contents: {}",
+                failures.join("; ")
+            )));
+        }
+
         let d1 = Utc::now() - Duration::days(req.interval as _);
         let d2 = d1 + Duration::days(1);
         let query = QueryRequest::new_with_dt("created_at", d1, d2);
@@ -21,47 +43,125 @@ impl CrmService {
             .get()
             .await
             .map_err(|e| Status::internal(e.to_string()))?
-            .query(query)
+            .query(authorized(query, token)?)
             .await?
             .into_inner();
 
-        let contents = self
-            .metadata_pool
-            .get()
-            .await
-            .map_err(|e| Status::internal(e.to_string()))?
-            .materialize(MaterializeRequest::new_with_ids(&req.content_ids))
-            .await?
-            .into_inner();
-
-        let contents: Vec<Content> = contents
-            .filter_map(|v| async move { v.ok() })
-            .collect()
-            .await;
-
         debug!("contents: {:?}", contents);
 
-        let sender = self.config.server.sender_email.clone();
+        let template = welcome_email(self.config.server.sender_email.clone(), &contents);
+        // the requests are polled by the client connection, out of the handler span
+        let span = Span::current();
         let reqs = res_user_stats.filter_map(move |v| {
-            let sender: String = sender.clone();
+            let template = template.clone();
+            let span = span.clone();
             async move {
                 let v = v.ok()?;
                 debug!("sending email to {}", v.email);
-                Some(SendRequest::new_email(
-                    "Welcome".to_string(),
-                    sender,
-                    &[v.email],
-                ))
+                Some(SendRequest::from(EmailMessage {
+                    message_id: Uuid::new_v4().to_string(),
+                    recipients: vec![v.email],
+                    ..template
+                }))
             }
+            .instrument(span)
         });
 
         self.notification_pool
             .get()
             .await
             .map_err(|e| Status::internal(e.to_string()))?
-            .send(reqs)
+            .send(authorized(reqs, token)?)
             .await?;
 
-        Ok(Response::new(WelcomeResponse { id: request_id }))
+        Ok(Response::new(WelcomeResponse {
+            id: request_id,
+            warnings: failures,
+        }))
+    }
+}
+
+/// Build the welcome email without recipients, listing the contents in a text
+/// fallback and in html with their thumbnails embedded inline, the contents
+/// without image are listed by name
+fn welcome_email(sender: String, contents: &[Content]) -> EmailMessage {
+    let body = contents
+        .iter()
+        .map(|c| format!("- {}: {}", c.name, c.url))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let items = contents
+        .iter()
+        .map(|c| {
+            let link = if c.image.is_empty() {
+                escape_html(&c.name)
+            } else {
+                format!(
+                    r#"<img src="cid:content-{}" alt="{}">"#,
+                    c.id,
+                    escape_html(&c.name)
+                )
+            };
+            format!(
+                r#"<li><a href="{}">{link}</a><p>{}</p></li>"#,
+                escape_html(&c.url),
+                escape_html(&c.description)
+            )
+        })
+        .collect::<String>();
+
+    let attachments = contents
+        .iter()
+        .filter(|c| !c.image.is_empty())
+        .map(|c| Attachment {
+            content_id: format!("content-{}", c.id),
+            source: Some(Source::Url(c.image.clone())),
+            ..Default::default()
+        })
+        .collect();
+
+    EmailMessage {
+        subject: "Welcome".to_string(),
+        sender,
+        body: format!("Welcome! Here is something to watch:\n{body}"),
+        html: format!("<h1>Welcome!</h1><p>Here is something to watch:</p><ul>{items}</ul>"),
+        attachments,
+        ..Default::default()
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contents_without_image_should_not_be_attached() {
+        let contents = vec![
+            Content {
+                id: 1,
+                name: "with image".to_string(),
+                image: "https://placehold.co/1600x900".to_string(),
+                ..Default::default()
+            },
+            Content {
+                id: 2,
+                name: "without image".to_string(),
+                ..Default::default()
+            },
+        ];
+        let email = welcome_email("crm@example.com".to_string(), &contents);
+
+        assert_eq!(email.attachments.len(), 1);
+        assert_eq!(email.attachments[0].content_id, "content-1");
+        assert!(!email.html.contains("cid:content-2"));
+        assert!(email.html.contains("without image"));
     }
 }
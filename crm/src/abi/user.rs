@@ -0,0 +1,218 @@
+use chrono::{DateTime, Utc};
+use prost_types::Timestamp;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Response, Status};
+use tracing::{info, warn, Instrument};
+use user_stat::{UserRecord, UserStore};
+
+use crate::{
+    pb::{CreateUserRequest, GetUserRequest, User},
+    CrmUserService,
+};
+
+/// Number of users loaded from the store at once when streaming
+const PAGE_SIZE: u32 = 100;
+const CHANNEL_SIZE: usize = 128;
+
+impl<S: UserStore> CrmUserService<S> {
+    pub async fn get_user(&self, req: GetUserRequest) -> Result<Response<User>, Status> {
+        let user = self
+            .store
+            .get_user(req.id)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .ok_or_else(|| Status::not_found(format!("User {} not found", req.id)))?;
+
+        Ok(Response::new(user.into()))
+    }
+
+    /// Create a user registered now, the email must not be taken
+    pub async fn create_user(&self, req: CreateUserRequest) -> Result<Response<User>, Status> {
+        let name = req.name.trim();
+        if name.is_empty() {
+            return Err(Status::invalid_argument("Name is required"));
+        }
+        let email = req.email.trim().to_lowercase();
+        if !email.contains('@') {
+            return Err(Status::invalid_argument(format!(
+                "Invalid email {}",
+                req.email
+            )));
+        }
+
+        let user = self
+            .store
+            .create_user(name, &email)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .ok_or_else(|| Status::already_exists(format!("User {email} already exists")))?;
+        info!("Created user {}", user.id);
+
+        Ok(Response::new(user.into()))
+    }
+
+    /// Stream the users whose id is not less than the requested one, ordered by id
+    pub fn server_side_streaming(
+        &self,
+        req: GetUserRequest,
+    ) -> ReceiverStream<Result<User, Status>> {
+        let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+        let store = self.store.clone();
+        let task = async move {
+            let mut from_id = req.id;
+            loop {
+                let users = match store.list_users(from_id, PAGE_SIZE).await {
+                    Ok(users) => users,
+                    Err(e) => {
+                        warn!("Failed to list users: {:?}", e);
+                        let _ = tx.send(Err(Status::internal(e.to_string()))).await;
+                        return;
+                    }
+                };
+
+                let next_id = users.last().and_then(|u| u.id.checked_add(1));
+                let last_page = users.len() < PAGE_SIZE as usize;
+                for user in users {
+                    if tx.send(Ok(user.into())).await.is_err() {
+                        // the client is gone
+                        return;
+                    }
+                }
+                match next_id {
+                    Some(next_id) if !last_page => from_id = next_id,
+                    _ => return,
+                }
+            }
+        };
+        tokio::spawn(task.in_current_span());
+
+        ReceiverStream::new(rx)
+    }
+}
+
+impl From<UserRecord> for User {
+    fn from(user: UserRecord) -> Self {
+        User {
+            id: user.id,
+            name: user.name,
+            email: user.email,
+            created_at: user.created_at.map(to_ts),
+        }
+    }
+}
+
+fn to_ts(date: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: date.timestamp(),
+        nanos: date.timestamp_subsec_nanos() as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use anyhow::Result;
+    use crm_core::{auth, ConfigExt};
+    use futures::StreamExt;
+    use tonic::Code;
+
+    use super::*;
+    use crate::AppConfig;
+
+    #[derive(Default)]
+    struct MemoryStore {
+        users: Mutex<Vec<UserRecord>>,
+    }
+
+    impl UserStore for MemoryStore {
+        async fn get_user(&self, id: u64) -> Result<Option<UserRecord>> {
+            let users = self.users.lock().unwrap();
+            Ok(users.iter().find(|u| u.id == id).cloned())
+        }
+
+        async fn create_user(&self, name: &str, email: &str) -> Result<Option<UserRecord>> {
+            let mut users = self.users.lock().unwrap();
+            if users.iter().any(|u| u.email == email) {
+                return Ok(None);
+            }
+            let user = UserRecord {
+                id: users.len() as u64 + 1,
+                name: name.to_string(),
+                email: email.to_string(),
+                created_at: Some(Utc::now()),
+            };
+            users.push(user.clone());
+            Ok(Some(user))
+        }
+
+        async fn list_users(&self, from_id: u64, limit: u32) -> Result<Vec<UserRecord>> {
+            let users = self.users.lock().unwrap();
+            Ok(users
+                .iter()
+                .filter(|u| u.id >= from_id)
+                .take(limit as _)
+                .cloned()
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn created_user_should_be_found() {
+        let svc = CrmUserService::new(MemoryStore::default());
+        let req = CreateUserRequest {
+            name: "Alice".to_string(),
+            email: " Alice@Example.com ".to_string(),
+        };
+        let user = svc.create_user(req.clone()).await.unwrap().into_inner();
+        assert_eq!(user.email, "alice@example.com");
+
+        let ret = svc.get_user(GetUserRequest { id: user.id }).await.unwrap();
+        assert_eq!(ret.into_inner(), user);
+
+        let ret = svc.create_user(req).await.unwrap_err();
+        assert_eq!(ret.code(), Code::AlreadyExists);
+        let ret = svc.get_user(GetUserRequest { id: 42 }).await.unwrap_err();
+        assert_eq!(ret.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn users_should_be_streamed_across_pages() {
+        let svc = CrmUserService::new(MemoryStore::default());
+        for i in 0..PAGE_SIZE + 10 {
+            let req = CreateUserRequest {
+                name: format!("user{i}"),
+                email: format!("user{i}@example.com"),
+            };
+            svc.create_user(req).await.unwrap();
+        }
+
+        let ret: Vec<_> = svc
+            .server_side_streaming(GetUserRequest { id: 5 })
+            .map(|u| u.unwrap().id)
+            .collect()
+            .await;
+        assert_eq!(ret.len(), PAGE_SIZE as usize + 6);
+        assert_eq!(ret[0], 5);
+        assert_eq!(*ret.last().unwrap(), PAGE_SIZE as u64 + 10);
+    }
+
+    #[test]
+    fn user_service_should_require_scopes_of_the_config() {
+        let policy = AppConfig::load().unwrap().auth.policy;
+        let caller = |roles: &[&str]| auth::User {
+            name: "John Doe".to_string(),
+            email: "john.doe@example.com".to_string(),
+            roles: roles.iter().map(|s| s.to_string()).collect(),
+            scopes: vec![],
+        };
+
+        for method in ["GetUser", "CreateUser", "ServerSideStreaming"] {
+            let path = format!("/crm.UserService/{method}");
+            let err = policy.authorize(&path, Some(&caller(&[]))).unwrap_err();
+            assert_eq!(Status::from(err).code(), Code::PermissionDenied);
+            assert!(policy.authorize(&path, Some(&caller(&["admin"]))).is_ok());
+        }
+    }
+}
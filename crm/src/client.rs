@@ -1,5 +1,12 @@
-use anyhow::Result;
-use crm::pb::{crm_client::CrmClient, WelcomeRequestBuilder};
+use anyhow::{Context, Result};
+use crm::{
+    pb::{crm_client::CrmClient, WelcomeRequestBuilder},
+    AppConfig,
+};
+use crm_core::{
+    auth::{set_bearer_token, User},
+    ConfigExt,
+};
 use tonic::{transport::Channel, Request};
 use uuid::Uuid;
 
@@ -17,7 +24,19 @@ async fn main() -> Result<()> {
         .content_ids(vec![1, 2, 3])
         .build()?;
 
-    let response = client.welcome(Request::new(req)).await?.into_inner();
+    // sign with the key file of the service tokens
+    let config = AppConfig::load()?;
+    let key = config.auth.encoding_key()?;
+    let token = key.context("auth.sk is not configured")?.sign(User {
+        name: "John Doe".to_string(),
+        email: "john.doe@example.com".to_string(),
+        roles: vec![],
+        scopes: vec!["campaign:run".to_string()],
+    })?;
+    let mut req = Request::new(req);
+    set_bearer_token(&mut req, &token)?;
+
+    let response = client.welcome(req).await?.into_inner();
     println!("Response: {:?}", response);
     Ok(())
 }
@@ -1,5 +1,6 @@
-use crm_core::telemetry;
+use crm_core::{auth::AuthConfig, telemetry, TlsConfig};
 use serde::{Deserialize, Serialize};
+use user_stat::DBType;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppConfig {
@@ -8,11 +9,6 @@ pub struct AppConfig {
     pub telemetry: telemetry::Config,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AuthConfig {
-    pub pk: String,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub port: u16,
@@ -20,11 +16,16 @@ pub struct ServerConfig {
     pub metadata: String,
     pub user_stats: String,
     pub notification: String,
+    /// the user store queried by user-stat
+    pub users: UsersConfig,
+    /// serve over TLS and call the downstream services with its certificate if
+    /// set, their addresses are `https://` then
     pub tls: Option<TlsConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct TlsConfig {
-    pub cert: String,
-    pub key: String,
+pub struct UsersConfig {
+    pub db_url: String,
+    pub db_name: String,
+    pub db_type: DBType,
 }
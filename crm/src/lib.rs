@@ -5,6 +5,7 @@ pub mod pb;
 
 pub use config::AppConfig;
 
+use abi::ServiceTokens;
 use anyhow::{Context, Result};
 use crm_core::SendTrace;
 use crm_metadata::pb::metadata_client::MetadataClient;
@@ -13,16 +14,19 @@ use mobc::Manager;
 use mobc::Pool;
 use pb::{
     crm_server::{Crm, CrmServer},
-    RecallRequest, RecallResponse, RemindRequest, RemindResponse, WelcomeRequest, WelcomeResponse,
+    user_service_server::{UserService, UserServiceServer},
+    CreateUserRequest, GetUserRequest, RecallRequest, RecallResponse, RemindRequest,
+    RemindResponse, User, WelcomeRequest, WelcomeResponse,
 };
-use std::time::Duration;
-use tonic::transport::Endpoint;
+use std::{sync::Arc, time::Duration};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::{ClientTlsConfig, Endpoint};
 use tonic::{
     async_trait, service::interceptor::InterceptedService, transport::Channel, Request, Response,
     Status,
 };
 use tracing::instrument;
-use user_stat::pb::user_stats_client::UserStatsClient;
+use user_stat::{pb::user_stats_client::UserStatsClient, UserStore};
 
 /// A service for managing CRM operations.
 ///
@@ -36,6 +40,8 @@ pub struct CrmService {
     notification_pool:
         Pool<GrpcClientManager<NotificationClient<InterceptedService<Channel, SendTrace>>>>,
     metadata_pool: Pool<GrpcClientManager<MetadataClient<InterceptedService<Channel, SendTrace>>>>,
+    /// the tokens of the downstream calls
+    service_tokens: ServiceTokens,
 }
 
 #[async_trait]
@@ -45,7 +51,8 @@ impl Crm for CrmService {
         &self,
         request: Request<WelcomeRequest>,
     ) -> Result<Response<WelcomeResponse>, Status> {
-        self.welcome(request.into_inner()).await
+        let token = self.downstream_token()?;
+        self.welcome(request.into_inner(), &token).await
     }
 
     #[instrument(name = "recall_handler", skip_all)]
@@ -65,17 +72,87 @@ impl Crm for CrmService {
     }
 }
 
+/// A service managing the users of the store queried by user-stat, so that the
+/// users created through it are visible to the campaigns.
+pub struct CrmUserService<S> {
+    store: Arc<S>,
+}
+
+#[async_trait]
+impl<S: UserStore> UserService for CrmUserService<S> {
+    type ServerSideStreamingStream = ReceiverStream<Result<User, Status>>;
+
+    #[instrument(name = "get_user_handler", skip_all)]
+    async fn get_user(&self, request: Request<GetUserRequest>) -> Result<Response<User>, Status> {
+        self.get_user(request.into_inner()).await
+    }
+
+    #[instrument(name = "create_user_handler", skip_all)]
+    async fn create_user(
+        &self,
+        request: Request<CreateUserRequest>,
+    ) -> Result<Response<User>, Status> {
+        self.create_user(request.into_inner()).await
+    }
+
+    #[instrument(name = "server_side_streaming_handler", skip_all)]
+    async fn server_side_streaming(
+        &self,
+        request: Request<GetUserRequest>,
+    ) -> Result<Response<Self::ServerSideStreamingStream>, Status> {
+        Ok(Response::new(
+            self.server_side_streaming(request.into_inner()),
+        ))
+    }
+}
+
+impl<S: UserStore> CrmUserService<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store: Arc::new(store),
+        }
+    }
+
+    pub fn into_server(self) -> UserServiceServer<Self> {
+        UserServiceServer::new(self)
+    }
+}
+
+impl<S> Clone for CrmUserService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+        }
+    }
+}
+
 impl CrmService {
     pub async fn try_new(config: AppConfig) -> Result<Self> {
-        let user_stats_pool = create_client_pool(&config.server.user_stats).await?;
-        let notification_pool = create_client_pool(&config.server.notification).await?;
-        let metadata_pool = create_client_pool(&config.server.metadata).await?;
+        // the downstream services are called with the certificate of crm
+        let tls = config
+            .server
+            .tls
+            .as_ref()
+            .map(|tls| tls.client_config())
+            .transpose()?;
+        let user_stats_pool = create_client_pool(&config.server.user_stats, tls.clone()).await?;
+        let notification_pool =
+            create_client_pool(&config.server.notification, tls.clone()).await?;
+        let metadata_pool = create_client_pool(&config.server.metadata, tls).await?;
+        // the downstream services grant their scopes to the role of the service
+        // tokens, the scopes of the callers are those of crm only
+        let key = config
+            .auth
+            .encoding_key()?
+            .context("auth.sk is required to sign the tokens of the downstream calls")?;
+        let service_tokens = ServiceTokens::new(key, config.server.sender_email.clone());
 
         Ok(Self {
             config,
             user_stats_pool,
             notification_pool,
             metadata_pool,
+            service_tokens,
         })
     }
 
@@ -84,13 +161,17 @@ impl CrmService {
     }
 }
 
-async fn create_client_pool<T>(addr: &str) -> Result<Pool<GrpcClientManager<T>>>
+async fn create_client_pool<T>(
+    addr: &str,
+    tls: Option<ClientTlsConfig>,
+) -> Result<Pool<GrpcClientManager<T>>>
 where
     T: FromInterceptedService<InterceptedService<Channel, SendTrace>> + Send + 'static,
     T: Sync,
 {
     let manager = GrpcClientManager {
         addr: addr.to_string(),
+        tls,
         _phantom: std::marker::PhantomData,
     };
 
@@ -106,6 +187,8 @@ where
 /// A custom mobc manager for gRPC clients
 struct GrpcClientManager<T> {
     addr: String,
+    /// TLS of the connections, the client certificate is presented to the server
+    tls: Option<ClientTlsConfig>,
     _phantom: std::marker::PhantomData<T>,
 }
 
@@ -121,13 +204,14 @@ where
     type Error = anyhow::Error;
 
     async fn connect(&self) -> Result<Self::Connection, Self::Error> {
-        let channel = Endpoint::from_shared(self.addr.clone())?
-            .connect()
-            .await
-            .context(format!(
-                "Failed to connect to gRPC server with address: {}",
-                self.addr
-            ))?;
+        let mut endpoint = Endpoint::from_shared(self.addr.clone())?;
+        if let Some(tls) = &self.tls {
+            endpoint = endpoint.tls_config(tls.clone())?;
+        }
+        let channel = endpoint.connect().await.context(format!(
+            "Failed to connect to gRPC server with address: {}",
+            self.addr
+        ))?;
 
         Ok(T::from_intercepted_service(InterceptedService::new(
             channel, SendTrace,
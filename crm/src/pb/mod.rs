@@ -1,3 +1,6 @@
 mod crm;
 
 pub use self::crm::*;
+
+/// Encoded descriptors of the protos, served by the reflection service
+pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!("crm_descriptor.bin");
@@ -1,14 +1,31 @@
-use std::{mem, panic};
+use std::panic;
 
 use anyhow::Result;
-use crm::{AppConfig, CrmService};
-use crm_core::{log_error, shutdown_signal, telemetry, ConfigExt};
-use tonic::transport::{Identity, Server, ServerTlsConfig};
+use clickhouse::Client;
+use crm::{
+    pb::{crm_server, user_service_server, FILE_DESCRIPTOR_SET},
+    AppConfig, CrmService, CrmUserService,
+};
+use crm_core::{
+    accept_trace,
+    auth::AuthLayer,
+    health::{check_serving, report_health, report_ping, Ping},
+    log_error, make_span,
+    metrics::GrpcMetricsLayer,
+    shutdown_signal, telemetry, ConfigExt,
+};
+use crm_metadata::pb::metadata_server;
+use crm_notification::pb::notification_server;
+use tonic::transport::{ClientTlsConfig, Server};
+use tonic_health::server::HealthReporter;
+use tower::ServiceBuilder;
+use tower_http::trace::TraceLayer;
 use tracing::{error, info};
+use user_stat::{pb::user_stats_server, ClickHouseRepo, DBType, PostgresRepo};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let mut config = AppConfig::load().expect("Failed to load config");
+    let config = AppConfig::load().expect("Failed to load config");
 
     // Initialize tracing & logging.
     telemetry::init(config.telemetry.clone()).inspect_err(log_error)?;
@@ -16,27 +33,123 @@ async fn main() -> Result<()> {
     // Replace the default panic hook with one that uses structured logging at ERROR level.
     panic::set_hook(Box::new(|panic| error!(%panic, "process panicked")));
 
-    let tls = mem::take(&mut config.server.tls);
-
     let addr = config.server.port;
     let addr = format!("127.0.0.1:{}", addr).parse().unwrap();
     info!("CRM service listening on {}", addr);
+
+    let dk = config.auth.decoding_key()?;
+    let authz = config.auth.authz_layer();
+
+    let server = match &config.server.tls {
+        // if tls is enabled, use tls
+        Some(tls) => Server::builder().tls_config(tls.server_config()?)?,
+        None => {
+            info!("TLS is not enabled");
+            Server::builder()
+        }
+    };
+    // the callers are authenticated by every service, not only by the proxy
+    let mut server = server.layer(
+        ServiceBuilder::new()
+            .layer(GrpcMetricsLayer::new(&[
+                FILE_DESCRIPTOR_SET,
+                tonic_reflection::pb::v1::FILE_DESCRIPTOR_SET,
+            ])?)
+            .layer(
+                TraceLayer::new_for_grpc()
+                    .make_span_with(make_span)
+                    .on_request(accept_trace),
+            )
+            .layer(AuthLayer::new(dk))
+            .layer(authz),
+    );
+    let (reporter, health) = tonic_health::server::health_reporter();
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .build_v1()?;
+    let server = server.add_service(health).add_service(reflection);
+
+    let downstream = Downstream {
+        services: vec![
+            (
+                config.server.user_stats.clone(),
+                user_stats_server::SERVICE_NAME,
+            ),
+            (
+                config.server.metadata.clone(),
+                metadata_server::SERVICE_NAME,
+            ),
+            (
+                config.server.notification.clone(),
+                notification_server::SERVICE_NAME,
+            ),
+        ],
+        tls: config
+            .server
+            .tls
+            .as_ref()
+            .map(|tls| tls.client_config())
+            .transpose()?,
+    };
+
+    // the user service is backed by the user store of user-stat
+    let users = &config.server.users;
+    let router = match users.db_type {
+        DBType::Clickhouse => {
+            let repo = ClickHouseRepo::new(
+                Client::default()
+                    .with_url(&users.db_url)
+                    .with_database(&users.db_name),
+            );
+            report_ready(reporter, downstream, repo.clone());
+            server.add_service(CrmUserService::new(repo).into_server())
+        }
+        DBType::Postgres => {
+            let repo = PostgresRepo::new(&format!("{}/{}", users.db_url, users.db_name)).await?;
+            report_ready(reporter, downstream, repo.clone());
+            server.add_service(CrmUserService::new(repo).into_server())
+        }
+    };
+
     let svc = CrmService::try_new(config).await?.into_server()?;
+    router
+        .add_service(svc)
+        .serve_with_shutdown(addr, shutdown_signal())
+        .await?;
+    Ok(())
+}
+
+/// The services called by the campaigns
+#[derive(Clone)]
+struct Downstream {
+    /// addresses and names of the services
+    services: Vec<(String, &'static str)>,
+    tls: Option<ClientTlsConfig>,
+}
 
-    // if tls is enabled, use tls
-    if let Some(tls) = tls {
-        let identity = Identity::from_pem(tls.cert, tls.key);
-        Server::builder()
-            .tls_config(ServerTlsConfig::new().identity(identity))?
-            .add_service(svc)
-            .serve(addr)
-            .await?;
-    } else {
-        info!("TLS is not enabled");
-        Server::builder()
-            .add_service(svc)
-            .serve_with_shutdown(addr, shutdown_signal())
-            .await?;
+impl Downstream {
+    async fn check(&self) -> Result<()> {
+        for (addr, service) in &self.services {
+            check_serving(addr, service, self.tls.as_ref()).await?;
+        }
+        Ok(())
     }
-    Ok(())
+}
+
+/// Report the user service as ready while the user store is reachable, and the
+/// campaigns and the server as a whole while the downstream services are serving too
+fn report_ready(reporter: HealthReporter, downstream: Downstream, users: impl Ping) {
+    report_ping(
+        reporter.clone(),
+        vec![user_service_server::SERVICE_NAME],
+        users.clone(),
+    );
+    report_health(reporter, vec![crm_server::SERVICE_NAME, ""], move || {
+        let users = users.clone();
+        let downstream = downstream.clone();
+        async move {
+            users.ping().await?;
+            downstream.check().await
+        }
+    });
 }
@@ -0,0 +1,133 @@
+use std::{fs, io::Write, path::PathBuf};
+
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use crm_core::auth::{EncodingKey, Jwk, Jwks, TokenOptions, User};
+
+/// environment variable of the private key pem, used if no key file is given
+const SK_ENV: &str = "CRM_SK";
+
+/// Issue and revoke the tokens of the crm services
+#[derive(Debug, Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Sign a token and print it
+    Sign(SignArgs),
+    /// Revoke a token by its id or all the tokens of a subject
+    Revoke {
+        /// id (jti) or subject of the tokens
+        id: String,
+        /// the deny-list configured in the services
+        #[arg(long)]
+        deny_list: PathBuf,
+    },
+    /// Print the JWKS document of the public key of the private key
+    Jwks(KeyArgs),
+    /// Generate a private key into a new file and print its public key
+    Keygen {
+        /// pem file of the private key, keep it out of the repository
+        #[arg(long)]
+        out: PathBuf,
+    },
+}
+
+#[derive(Debug, Args)]
+struct SignArgs {
+    #[arg(long)]
+    name: String,
+    #[arg(long)]
+    email: String,
+    #[arg(long = "role")]
+    roles: Vec<String>,
+    #[arg(long = "scope")]
+    scopes: Vec<String>,
+    /// lifetime of the token in seconds
+    #[arg(long)]
+    expiry: Option<u64>,
+    #[arg(long)]
+    audience: Option<String>,
+    /// subject of the token, the email if not set
+    #[arg(long)]
+    subject: Option<String>,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+#[derive(Debug, Args)]
+struct KeyArgs {
+    /// pem file of the private key, read from `CRM_SK` if not set
+    #[arg(long)]
+    key: Option<PathBuf>,
+    /// key id of the tokens
+    #[arg(long)]
+    kid: Option<String>,
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Sign(args) => {
+            let key = args.key.load()?;
+            let defaults = TokenOptions::default();
+            let opts = TokenOptions {
+                expiry: args.expiry.unwrap_or(defaults.expiry),
+                audience: args.audience.unwrap_or(defaults.audience),
+                subject: args.subject,
+            };
+            let user = User {
+                name: args.name,
+                email: args.email,
+                roles: args.roles,
+                scopes: args.scopes,
+            };
+            println!("{}", key.issue(user, &opts)?);
+        }
+        Command::Revoke { id, deny_list } => {
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&deny_list)
+                .with_context(|| format!("failed to open {}", deny_list.display()))?;
+            writeln!(file, "{id}")?;
+        }
+        Command::Jwks(args) => {
+            let jwks = Jwks {
+                keys: vec![Jwk::from_public_key(&args.load()?.public_key())],
+            };
+            println!("{}", serde_json::to_string_pretty(&jwks)?);
+        }
+        Command::Keygen { out } => {
+            let key = EncodingKey::generate();
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            options
+                .open(&out)
+                .and_then(|mut file| file.write_all(key.to_pem().as_bytes()))
+                .with_context(|| format!("failed to write {}", out.display()))?;
+            print!("{}", key.public_key().to_pem());
+        }
+    }
+    Ok(())
+}
+
+impl KeyArgs {
+    fn load(&self) -> Result<EncodingKey> {
+        let key = match &self.key {
+            Some(path) => EncodingKey::from_file(path)?,
+            None => EncodingKey::load(
+                &std::env::var(SK_ENV)
+                    .with_context(|| format!("no key file given and {SK_ENV} is not set"))?,
+            )?,
+        };
+        Ok(match &self.kid {
+            Some(kid) => key.with_key_id(kid),
+            None => key,
+        })
+    }
+}
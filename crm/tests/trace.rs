@@ -0,0 +1,169 @@
+use std::{convert::Infallible, fs::File, net::SocketAddr, time::Duration};
+
+use anyhow::Result;
+use crm::{
+    pb::{crm_client::CrmClient, WelcomeRequest},
+    AppConfig, CrmService,
+};
+use crm_core::{accept_trace, auth::EncodingKey, make_span, ConfigExt, SendTrace};
+use crm_metadata::{FakeRepo, MetadataService};
+use crm_notification::{MemoryRepo, NotificationService};
+use futures::stream::{self, Empty};
+use opentelemetry::{global, trace::TracerProvider as _};
+use opentelemetry_sdk::{
+    export::trace::SpanData,
+    propagation::TraceContextPropagator,
+    testing::trace::InMemorySpanExporter,
+    trace::{Tracer, TracerProvider},
+};
+use tokio::{net::TcpListener, time::sleep};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{
+    async_trait,
+    body::BoxBody,
+    codegen::{http, Service},
+    server::NamedService,
+    transport::{Endpoint, Server},
+    Request, Response, Status,
+};
+use tower_http::trace::TraceLayer;
+use tracing::{info_span, Instrument};
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt};
+use user_stat::pb::{
+    user_stats_server::{UserStats, UserStatsServer},
+    QueryRequest, RawQueryRequest, User,
+};
+
+#[tokio::test]
+async fn welcome_should_be_traced_under_the_span_of_the_caller() -> Result<()> {
+    let exporter = InMemorySpanExporter::default();
+    let provider = TracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let _guard = tracing::subscriber::set_default(subscriber(provider.tracer("test")));
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let addr = start_servers().await?;
+    let channel = Endpoint::from_shared(format!("http://{addr}"))?
+        .connect()
+        .await?;
+    let mut client = CrmClient::with_interceptor(channel, SendTrace);
+
+    async {
+        let req = WelcomeRequest {
+            id: "welcome".to_string(),
+            interval: 7,
+            content_ids: vec![1, 2, 3],
+            strict: false,
+        };
+        client.welcome(Request::new(req)).await?;
+        Ok::<_, anyhow::Error>(())
+    }
+    .instrument(info_span!("caller"))
+    .await?;
+
+    // the spans of the metadata service end with the response stream
+    sleep(Duration::from_millis(100)).await;
+    let spans = exporter.get_finished_spans()?;
+    let span = |name: &str| {
+        spans
+            .iter()
+            .find(|s| s.name == name)
+            .unwrap_or_else(|| panic!("span {name} is exported"))
+    };
+    // every service has an incoming request span, the one under the given parent
+    let child_of = |name: &str, parent: &SpanData| {
+        spans
+            .iter()
+            .find(|s| s.name == name && s.parent_span_id == parent.span_context.span_id())
+            .unwrap_or_else(|| panic!("span {name} is exported under {}", parent.name))
+    };
+
+    let caller = span("caller");
+    let welcome = child_of("welcome_handler", child_of("incoming request", caller));
+    let materialize = child_of(
+        "materialize",
+        child_of("materialize-handler", child_of("incoming request", welcome)),
+    );
+    let repo = child_of("content-materialize", materialize);
+    assert_eq!(repo.span_context.trace_id(), caller.span_context.trace_id());
+
+    Ok(())
+}
+
+fn subscriber(tracer: Tracer) -> impl tracing::Subscriber + Send + Sync {
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// A user-stat service without users, no email is sent
+struct NoUsers;
+
+#[async_trait]
+impl UserStats for NoUsers {
+    type QueryStream = Empty<Result<User, Status>>;
+    type RawQueryStream = Empty<Result<User, Status>>;
+
+    async fn query(
+        &self,
+        _request: Request<QueryRequest>,
+    ) -> Result<Response<Self::QueryStream>, Status> {
+        Ok(Response::new(stream::empty()))
+    }
+
+    async fn raw_query(
+        &self,
+        _request: Request<RawQueryRequest>,
+    ) -> Result<Response<Self::RawQueryStream>, Status> {
+        Ok(Response::new(stream::empty()))
+    }
+}
+
+/// Start crm and the services it calls, the address of crm is returned
+async fn start_servers() -> Result<SocketAddr> {
+    let config = serde_yaml::from_reader(File::open("../crm-metadata/app.yml")?)?;
+    let metadata = serve(MetadataService::new(FakeRepo::new(), config).into_server()).await?;
+    let config = serde_yaml::from_reader(File::open("../crm-notification/app.yml")?)?;
+    let notification =
+        serve(NotificationService::new(MemoryRepo::new(), config).into_server()).await?;
+    let user_stats = serve(UserStatsServer::new(NoUsers)).await?;
+
+    let sk = std::env::temp_dir().join(format!("crm-trace-sk-{}.pem", std::process::id()));
+    std::fs::write(&sk, EncodingKey::generate().to_pem())?;
+    let mut config = AppConfig::load()?;
+    config.auth.sk = Some(sk);
+    config.server.metadata = format!("http://{metadata}");
+    config.server.notification = format!("http://{notification}");
+    config.server.user_stats = format!("http://{user_stats}");
+    serve(CrmService::try_new(config).await?.into_server()?).await
+}
+
+/// Serve the service accepting the trace of the callers, as the services do
+async fn serve<S>(svc: S) -> Result<SocketAddr>
+where
+    S: Service<http::Request<BoxBody>, Response = http::Response<BoxBody>, Error = Infallible>
+        + NamedService
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    // the connections are accepted once the server runs, the listener is bound before
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        Server::builder()
+            .layer(
+                TraceLayer::new_for_grpc()
+                    .make_span_with(make_span)
+                    .on_request(accept_trace),
+            )
+            .add_service(svc)
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+
+    Ok(addr)
+}
@@ -1,16 +1,18 @@
 //! This example shows how to use the load balancer with a client.
 //! The client will send requests to the load balancer(localhost:8000), which will
-//! round robin between two upstream servers.
+//! route them to the notification upstream configured in `app.yml`, signing its
+//! token with the key file at `auth.sk` of `app.yml`.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use crm_core::ConfigExt;
 use crm_notification::pb::{
     notification_client::NotificationClient, EmailMessage, InAppMessage, SendRequest, SmsMessage,
 };
 use futures::StreamExt;
-use load_balancer::{EncodingKey, User, SK};
+use load_balancer::{set_bearer_token, AppConfig, User};
 use tonic::{
     transport::{Certificate, Channel, ClientTlsConfig},
-    Request,
+    Request, Status,
 };
 use tracing::{info, level_filters::LevelFilter};
 use tracing_subscriber::{
@@ -23,10 +25,14 @@ async fn main() -> Result<()> {
         .with_filter(EnvFilter::from_default_env().add_directive(LevelFilter::INFO.into()));
     tracing_subscriber::registry().with(layer).init();
 
-    let ek = EncodingKey::load(SK)?;
+    let config = AppConfig::load()?;
+    let ek = config.auth.encoding_key()?;
+    let ek = ek.context("auth.sk is not configured")?;
     let token = ek.sign(User {
         name: "John Doe".to_string(),
         email: "john.doe@example.com".to_string(),
+        roles: vec![],
+        scopes: vec!["notify:send".to_string()],
     })?;
 
     let pem = include_str!("../assets/cert/ca.crt");
@@ -42,21 +48,14 @@ async fn main() -> Result<()> {
 
     // attach the token to the request
     let mut client = NotificationClient::with_interceptor(channel, move |mut req: Request<()>| {
-        req.metadata_mut()
-            .insert("authorization", token.parse().unwrap());
+        set_bearer_token(&mut req, &token).map_err(|_| Status::internal("invalid token"))?;
         Ok(req)
     });
 
     let stream = tokio_stream::iter(vec![
-        SendRequest {
-            msg: Some(EmailMessage::default().into()),
-        },
-        SendRequest {
-            msg: Some(SmsMessage::default().into()),
-        },
-        SendRequest {
-            msg: Some(InAppMessage::default().into()),
-        },
+        SendRequest::from(EmailMessage::default()),
+        SendRequest::from(SmsMessage::default()),
+        SendRequest::from(InAppMessage::default()),
     ]);
     let request = Request::new(stream);
     let response = client.send(request).await?.into_inner();
@@ -0,0 +1,148 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use crm_core::{auth::AuthConfig, telemetry, TlsConfig};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    /// pools of backends, a request is routed to the pool of the longest prefix
+    /// matching its gRPC path
+    pub upstreams: Vec<UpstreamConfig>,
+    /// limits of the calls of each user, no limit if not set
+    pub rate_limit: Option<RateLimitConfig>,
+    #[serde(default)]
+    pub telemetry: telemetry::Config,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServerConfig {
+    pub listen: String,
+    pub cert: String,
+    pub key: String,
+    /// address of the Prometheus `/metrics` endpoint, no endpoint if not set
+    pub metrics: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamConfig {
+    /// name of the service, e.g. `notification`
+    pub name: String,
+    /// gRPC path prefix of the service, e.g. `/notification.Notification/`
+    pub prefix: String,
+    /// addresses of the instances of the service
+    #[serde(default)]
+    pub backends: Vec<String>,
+    /// discover the instances from a file instead of the static backends
+    pub discovery: Option<DiscoveryConfig>,
+    /// check the health of the instances, the unhealthy ones receive no request
+    pub health_check: Option<HealthCheckConfig>,
+    /// how an instance is selected for a request, round robin by default
+    #[serde(default)]
+    pub selection: SelectionConfig,
+    /// call the instances over TLS with the client certificate of the proxy
+    pub tls: Option<TlsConfig>,
+}
+
+/// ```yaml
+/// selection:
+///   type: consistent
+///   key:
+///     claim: email
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum SelectionConfig {
+    #[default]
+    RoundRobin,
+    /// consistent hashing (Ketama) of a key of the request, the requests of a key
+    /// are sent to the same instance while it is healthy, the requests without the
+    /// key are sent round robin
+    Consistent { key: HashKey },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashKey {
+    /// a request header, e.g. `x-session-id`
+    Header(String),
+    /// a claim of the token, `email` or `name`
+    Claim(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveryConfig {
+    /// file of the instance addresses, one per line
+    pub file: PathBuf,
+    /// interval in seconds between reads of the file
+    #[serde(default = "default_discovery_interval")]
+    pub interval: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheckConfig {
+    /// the service name checked, the overall health of the server if empty
+    #[serde(default)]
+    pub service: String,
+    /// interval in seconds between checks
+    #[serde(default = "default_health_check_interval")]
+    pub interval: u64,
+    /// timeout of a check in milliseconds
+    #[serde(default = "default_health_check_timeout")]
+    pub timeout: u64,
+    /// consecutive successful checks to flip an instance to healthy
+    #[serde(default = "default_threshold")]
+    pub healthy_threshold: usize,
+    /// consecutive failed checks to flip an instance to unhealthy
+    #[serde(default = "default_threshold")]
+    pub unhealthy_threshold: usize,
+}
+
+/// Token buckets of each user (the email of their token) and gRPC method
+///
+/// ```yaml
+/// rate_limit:
+///   default: { rate: 10, burst: 20 }
+///   methods:
+///     /notification.Notification/Send: { rate: 1, burst: 5 }
+///     # every method of the service
+///     /user_stat.UserStats: { rate: 5, burst: 10 }
+///   # share the buckets between the instances of the proxy
+///   redis: redis://127.0.0.1:6379
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// limit of the methods missing from `methods`, unlimited if not set
+    pub default: Option<LimitConfig>,
+    /// limits of a method (`/package.Service/Method`) or of every method of a
+    /// service (`/package.Service`)
+    #[serde(default)]
+    pub methods: HashMap<String, LimitConfig>,
+    /// keep the buckets in redis instead of in memory
+    pub redis: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LimitConfig {
+    /// tokens added to the bucket per second
+    pub rate: f64,
+    /// capacity of the bucket, the calls allowed in a burst
+    pub burst: u32,
+}
+
+fn default_discovery_interval() -> u64 {
+    10
+}
+
+fn default_health_check_interval() -> u64 {
+    5
+}
+
+fn default_health_check_timeout() -> u64 {
+    1000
+}
+
+fn default_threshold() -> usize {
+    1
+}
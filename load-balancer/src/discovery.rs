@@ -0,0 +1,62 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+};
+
+use pingora::{Error, ErrorType, Result};
+use pingora_load_balancing::{discovery::ServiceDiscovery, Backend};
+use tonic::async_trait;
+
+const DISCOVERY_ERROR: ErrorType = ErrorType::Custom("DiscoveryError");
+
+/// Backends listed in a file, one address per line, the file is read again on
+/// every update so that instances can be added or removed at runtime
+pub struct FileDiscovery {
+    path: PathBuf,
+}
+
+impl FileDiscovery {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl ServiceDiscovery for FileDiscovery {
+    async fn discover(&self) -> Result<(BTreeSet<Backend>, HashMap<u64, bool>)> {
+        let content = tokio::fs::read_to_string(&self.path).await.map_err(|e| {
+            Error::because(
+                DISCOVERY_ERROR,
+                format!("failed to read {}", self.path.display()),
+                e,
+            )
+        })?;
+        let backends = parse_backends(&content)?;
+        Ok((backends, HashMap::new()))
+    }
+}
+
+/// Parse the addresses of the backends, the empty lines and comments are ignored
+fn parse_backends(content: &str) -> Result<BTreeSet<Backend>> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(Backend::new)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backends_should_be_parsed_from_lines() {
+        let backends =
+            parse_backends("# notification\n127.0.0.1:50003\n\n127.0.0.1:50013\n").unwrap();
+        let addrs: Vec<_> = backends.iter().map(|b| b.addr.to_string()).collect();
+        assert_eq!(addrs, vec!["127.0.0.1:50003", "127.0.0.1:50013"]);
+
+        assert!(parse_backends("not an address").is_err());
+    }
+}
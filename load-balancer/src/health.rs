@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use pingora::{Error, ErrorType, Result};
+use pingora_load_balancing::{health_check::HealthCheck, Backend};
+use tonic::{
+    async_trait,
+    transport::{ClientTlsConfig, Endpoint},
+};
+use tonic_health::pb::{
+    health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+};
+
+use crate::HealthCheckConfig;
+
+const HEALTH_CHECK_ERROR: ErrorType = ErrorType::Custom("HealthCheckError");
+
+/// Check the backends with the `grpc.health.v1` protocol
+pub struct GrpcHealthCheck {
+    /// the service checked, the overall health of the server if empty
+    service: String,
+    timeout: Duration,
+    healthy_threshold: usize,
+    unhealthy_threshold: usize,
+    /// the backends are checked over TLS with the client certificate if set
+    tls: Option<ClientTlsConfig>,
+}
+
+impl GrpcHealthCheck {
+    pub fn new(config: &HealthCheckConfig, tls: Option<ClientTlsConfig>) -> Self {
+        Self {
+            service: config.service.clone(),
+            timeout: Duration::from_millis(config.timeout),
+            healthy_threshold: config.healthy_threshold.max(1),
+            unhealthy_threshold: config.unhealthy_threshold.max(1),
+            tls,
+        }
+    }
+
+    async fn status(&self, target: &Backend) -> Result<ServingStatus> {
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        let mut endpoint = Endpoint::from_shared(format!("{scheme}://{}", target.addr))
+            .map_err(|e| Error::because(HEALTH_CHECK_ERROR, "invalid backend address", e))?
+            .connect_timeout(self.timeout)
+            .timeout(self.timeout);
+        if let Some(tls) = &self.tls {
+            endpoint = endpoint
+                .tls_config(tls.clone())
+                .map_err(|e| Error::because(HEALTH_CHECK_ERROR, "invalid TLS config", e))?;
+        }
+        let channel = endpoint
+            .connect()
+            .await
+            .map_err(|e| Error::because(HEALTH_CHECK_ERROR, "failed to connect", e))?;
+
+        let res = HealthClient::new(channel)
+            .check(HealthCheckRequest {
+                service: self.service.clone(),
+            })
+            .await
+            .map_err(|e| Error::because(HEALTH_CHECK_ERROR, "failed to check health", e))?;
+        Ok(res.into_inner().status())
+    }
+}
+
+#[async_trait]
+impl HealthCheck for GrpcHealthCheck {
+    async fn check(&self, target: &Backend) -> Result<()> {
+        match self.status(target).await? {
+            ServingStatus::Serving => Ok(()),
+            status => Err(Error::explain(
+                HEALTH_CHECK_ERROR,
+                format!("{} is {}", target.addr, status.as_str_name()),
+            )),
+        }
+    }
+
+    fn health_threshold(&self, success: bool) -> usize {
+        if success {
+            self.healthy_threshold
+        } else {
+            self.unhealthy_threshold
+        }
+    }
+}
@@ -1,3 +1,20 @@
-mod auth;
+mod config;
+mod discovery;
+mod health;
+mod metrics;
+mod rate_limit;
+mod router;
+mod status;
+mod tls;
 
-pub use auth::{DecodingKey, EncodingKey, User, PK, SK};
+pub use config::{
+    AppConfig, DiscoveryConfig, HashKey, HealthCheckConfig, LimitConfig, RateLimitConfig,
+    SelectionConfig, ServerConfig, UpstreamConfig,
+};
+pub use crm_core::auth::{set_bearer_token, AuthConfig, DecodingKey, EncodingKey, User};
+pub use discovery::FileDiscovery;
+pub use health::GrpcHealthCheck;
+pub use metrics::{record_request, record_upstream_error};
+pub use rate_limit::RateLimiter;
+pub use router::{Router, Upstream};
+pub use status::{error_status, respond_status};
@@ -1,113 +1,286 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
+use anyhow::Context as _;
+use crm_core::{accept_headers_trace, log_error, telemetry, trace_headers, ConfigExt};
 use dotenv::dotenv;
-use load_balancer::{DecodingKey, PK};
-use pingora::protocols::ALPN;
+use load_balancer::{
+    error_status, record_request, record_upstream_error, respond_status, AppConfig, DecodingKey,
+    RateLimiter, Router, Upstream,
+};
 use pingora::server::{configuration::Opt, Server};
-use pingora::tls::x509::X509;
-use pingora::Result;
 use pingora::{
+    http::{RequestHeader, ResponseHeader},
     proxy::{ProxyHttp, Session},
+    services::listening::Service,
     upstreams::peer::HttpPeer,
 };
-use pingora_load_balancing::selection::RoundRobin;
-use pingora_load_balancing::LoadBalancer;
-use tonic::async_trait;
-use tracing::{info, Level};
+use pingora::{Error, ErrorSource, ErrorType, Result};
+use tonic::{async_trait, Status};
+use tracing::{debug, field, info, info_span, warn, Span};
+
+const GRPC_STATUS: &str = "grpc-status";
 
 pub struct GrpcProxy(Arc<GrpcProxyInner>);
 
+/// The state of a request
+pub struct RequestCtx {
+    upstream: Option<Arc<Upstream>>,
+    /// selects the backend of the upstream, the backends are taken in turn without it
+    key: Option<Vec<u8>>,
+    /// the email of the authenticated user, none until the request is authenticated
+    subject: Option<String>,
+    /// from the trailers of the upstream response, or the headers of a trailers-only response
+    grpc_status: Option<String>,
+    start: Instant,
+    /// the span of the request in the proxy, the parent of the upstream spans
+    span: Span,
+}
+
 pub struct GrpcProxyInner {
-    lb: LoadBalancer<RoundRobin>,
+    router: Router,
     dk: DecodingKey,
+    limiter: Option<RateLimiter>,
 }
 
-fn main() {
+fn main() -> anyhow::Result<()> {
     dotenv().ok();
 
-    tracing_subscriber::fmt().with_max_level(Level::INFO).init();
+    let mut config = AppConfig::load().expect("Failed to load config");
+
+    // the traces are exported from a runtime of their own, the proxy services
+    // run in the runtimes of pingora
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(async { telemetry::init(std::mem::take(&mut config.telemetry)) })
+        .inspect_err(log_error)
+        .unwrap();
 
     let opt = Opt::default();
     let mut server = Server::new(Some(opt)).unwrap();
     server.bootstrap();
 
-    let dk = DecodingKey::load(PK).unwrap();
-
-    let upstreams: LoadBalancer<RoundRobin> =
-        LoadBalancer::try_from_iter(["127.0.0.1:50002", "127.0.0.1:50003"]).unwrap();
+    // the keys are reloaded on the runtime of the traces too, pingora's runtimes
+    // only run the services
+    let dk = {
+        let _guard = runtime.enter();
+        config
+            .auth
+            .decoding_key()
+            .context("failed to load the public keys")?
+    };
+    let router = Router::try_new(&config.upstreams).unwrap();
+    let router_services = router.background_services();
+    let limiter = config
+        .rate_limit
+        .map(RateLimiter::try_new)
+        .transpose()
+        .unwrap();
 
     let mut grpc_proxy = pingora::proxy::http_proxy_service(
         &server.configuration,
-        GrpcProxy(Arc::new(GrpcProxyInner { lb: upstreams, dk })),
+        GrpcProxy(Arc::new(GrpcProxyInner {
+            router,
+            dk,
+            limiter,
+        })),
     );
 
-    let mut tls_settings = pingora::listeners::TlsSettings::intermediate(
-        "assets/cert/server.crt",
-        "assets/cert/server.key",
-    )
-    .unwrap();
+    let mut tls_settings =
+        pingora::listeners::TlsSettings::intermediate(&config.server.cert, &config.server.key)
+            .unwrap();
 
     // set alpn to h2 so that the client can use http2
     tls_settings.enable_h2();
 
-    grpc_proxy.add_tls_with_settings("0.0.0.0:8000", None, tls_settings);
+    grpc_proxy.add_tls_with_settings(&config.server.listen, None, tls_settings);
 
     server.add_service(grpc_proxy);
+    server.add_services(router_services);
+    if let Some(addr) = &config.server.metrics {
+        let mut metrics = Service::prometheus_http_service();
+        metrics.add_tcp(addr);
+        server.add_service(metrics);
+    }
 
     server.run_forever();
 }
 
 #[async_trait]
 impl ProxyHttp for GrpcProxy {
-    type CTX = ();
-    fn new_ctx(&self) -> Self::CTX {}
-
-    async fn request_filter(&self, session: &mut Session, _ctx: &mut Self::CTX) -> Result<bool> {
-        info!("request_filter");
-
-        let token = session.req_header().headers.get("authorization");
-
-        if let Some(token) = token {
-            match self.0.dk.verify(token.to_str().unwrap()) {
-                Ok(user) => {
-                    info!("Authenticated user: {:?}", user);
-                    Ok(false) // Continue processing the request
-                }
-                Err(e) => {
-                    info!("Authentication error: {:?}", e);
-                    let _ = session.respond_error(401).await;
-                    Ok(true) // Stop processing the request
-                }
+    type CTX = RequestCtx;
+    fn new_ctx(&self) -> Self::CTX {
+        RequestCtx {
+            upstream: None,
+            key: None,
+            subject: None,
+            grpc_status: None,
+            start: Instant::now(),
+            span: Span::none(),
+        }
+    }
+
+    async fn request_filter(&self, session: &mut Session, ctx: &mut Self::CTX) -> Result<bool> {
+        let path = session.req_header().uri.path().to_string();
+        ctx.span = info_span!(
+            "proxy request",
+            method = %path,
+            upstream = field::Empty,
+            trace_id = field::Empty
+        );
+        accept_headers_trace(&session.req_header().headers, &ctx.span);
+
+        let Some(upstream) = self.0.router.route(&path) else {
+            debug!(parent: &ctx.span, "no upstream for {path}");
+            let status = Status::unimplemented(format!("unknown method {path}"));
+            respond_status(session, status).await?;
+            return Ok(true);
+        };
+        ctx.upstream = Some(upstream.clone());
+        ctx.span.record("upstream", &upstream.name);
+
+        // a non-ASCII value is rejected as an invalid token
+        let authorization = session
+            .req_header()
+            .headers
+            .get("authorization")
+            .map(|v| v.to_str().unwrap_or_default());
+        let user = match self.0.dk.authenticate(authorization) {
+            Ok(user) => user,
+            Err(e) => {
+                debug!(parent: &ctx.span, "authentication error: {e}");
+                respond_status(session, e.into()).await?;
+                return Ok(true); // Stop processing the request
+            }
+        };
+        ctx.key = upstream.hash_key(session.req_header(), &user);
+        ctx.subject = Some(user.email);
+
+        if let Some(limiter) = &self.0.limiter {
+            let subject = ctx.subject.as_deref().unwrap_or_default();
+            if let Err(status) = limiter.check(subject, &path).await {
+                debug!(parent: &ctx.span, "{subject} is rate limited");
+                respond_status(session, status).await?;
+                return Ok(true);
             }
-        } else {
-            info!("Missing authorization token");
-            let _ = session.respond_error(401).await;
-            Ok(true) // Stop processing the request
         }
+        Ok(false) // Continue processing the request
     }
 
     async fn upstream_peer(
         &self,
         _session: &mut Session,
-        _ctx: &mut Self::CTX,
+        ctx: &mut Self::CTX,
     ) -> Result<Box<HttpPeer>> {
-        // get the upstream peer using round robin
-        let upstream = self
-            .0
-            .lb
-            .select(b"", 256) // key is ignored if the selection is random or round robin.
-            .unwrap();
+        // routed in request_filter
+        let upstream = ctx.upstream.as_ref().expect("request is routed");
+
+        let backend = upstream.select(ctx.key.as_deref()).ok_or_else(|| {
+            Error::explain(
+                ErrorType::HTTPStatus(503),
+                format!("no healthy backend of {}", upstream.name),
+            )
+        })?;
 
-        info!("upstream peer is: {:?}", upstream);
+        debug!(parent: &ctx.span, "upstream peer of {} is: {:?}", upstream.name, backend);
 
-        let mut peer = Box::new(HttpPeer::new(upstream, false, String::default()));
-        // set alpn to h2 so that the client can use http2
-        peer.options.alpn = ALPN::H2;
-        // trust the ca in assets/cert/ca.crt
-        peer.options.ca = Some(Arc::new(Box::from(vec![X509::from_pem(include_bytes!(
-            "../assets/cert/ca.crt"
-        ))
-        .unwrap()])));
+        let peer = Box::new(upstream.peer(backend));
         Ok(peer)
     }
+
+    /// Propagate the trace context of the proxy span to the upstream
+    async fn upstream_request_filter(
+        &self,
+        _session: &mut Session,
+        upstream_request: &mut RequestHeader,
+        ctx: &mut Self::CTX,
+    ) -> Result<()> {
+        for (name, value) in trace_headers(&ctx.span).iter() {
+            upstream_request.insert_header(name.clone(), value.clone())?;
+        }
+        Ok(())
+    }
+
+    fn upstream_response_filter(
+        &self,
+        _session: &mut Session,
+        upstream_response: &mut ResponseHeader,
+        ctx: &mut Self::CTX,
+    ) {
+        ctx.grpc_status = grpc_status(&upstream_response.headers);
+    }
+
+    fn upstream_response_trailer_filter(
+        &self,
+        _session: &mut Session,
+        upstream_trailers: &mut http::HeaderMap,
+        ctx: &mut Self::CTX,
+    ) -> Result<()> {
+        ctx.grpc_status = grpc_status(upstream_trailers).or(ctx.grpc_status.take());
+        Ok(())
+    }
+
+    /// Respond to the failures of the proxy with gRPC errors instead of HTTP errors
+    async fn fail_to_proxy(&self, session: &mut Session, e: &Error, _ctx: &mut Self::CTX) -> u16 {
+        let Some(status) = error_status(e) else {
+            // the client is gone
+            return 0;
+        };
+        warn!("proxy error: {e}");
+        if let Err(e) = respond_status(session, status).await {
+            warn!("failed to send the error response: {e}");
+        }
+        200
+    }
+
+    /// Write the access log and record the metrics of the request
+    async fn logging(&self, session: &mut Session, e: Option<&Error>, ctx: &mut Self::CTX) {
+        let latency = ctx.start.elapsed();
+        let method = session.req_header().uri.path();
+        let upstream = ctx.upstream.as_ref().map_or("none", |u| u.name.as_str());
+        // the proxy's own responses are trailers-only
+        let grpc_status = ctx
+            .grpc_status
+            .clone()
+            .or_else(|| {
+                session
+                    .response_written()
+                    .and_then(|r| grpc_status(&r.headers))
+            })
+            .unwrap_or_else(|| "unknown".to_string());
+        let http_status = session.response_written().map(|r| r.status.as_u16());
+
+        if let Some(e) = e.filter(|e| e.esource() == &ErrorSource::Upstream) {
+            record_upstream_error(upstream, e.etype().as_str());
+        }
+        // the paths of unauthenticated requests are not labelled by method, so that
+        // anonymous clients cannot create series with arbitrary paths
+        let label = if ctx.subject.is_some() {
+            method
+        } else {
+            "unknown"
+        };
+        record_request(upstream, label, &grpc_status, latency);
+
+        info!(
+            target: "access",
+            parent: &ctx.span,
+            method,
+            upstream,
+            subject = ctx.subject.as_deref(),
+            http_status,
+            grpc_status,
+            latency_ms = latency.as_secs_f64() * 1000.0,
+            bytes_in = session.body_bytes_read(),
+            bytes_out = session.body_bytes_sent(),
+            error = e.map(|e| e.to_string()),
+            "access"
+        );
+    }
+}
+
+fn grpc_status(headers: &http::HeaderMap) -> Option<String> {
+    headers
+        .get(GRPC_STATUS)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
 }
@@ -0,0 +1,45 @@
+use std::{sync::LazyLock, time::Duration};
+
+use prometheus::{register_histogram_vec, register_int_counter_vec, HistogramVec, IntCounterVec};
+
+static REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "grpc_proxy_requests_total",
+        "Requests proxied by upstream, method and gRPC status",
+        &["upstream", "method", "grpc_status"]
+    )
+    .expect("metric is registered once")
+});
+
+static LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "grpc_proxy_request_duration_seconds",
+        "Latency of the proxied requests by upstream and method",
+        &["upstream", "method"]
+    )
+    .expect("metric is registered once")
+});
+
+static UPSTREAM_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "grpc_proxy_upstream_errors_total",
+        "Failures to proxy a request to an upstream by error type",
+        &["upstream", "error"]
+    )
+    .expect("metric is registered once")
+});
+
+/// Record a request answered by the proxy or by an upstream
+pub fn record_request(upstream: &str, method: &str, grpc_status: &str, latency: Duration) {
+    REQUESTS
+        .with_label_values(&[upstream, method, grpc_status])
+        .inc();
+    LATENCY
+        .with_label_values(&[upstream, method])
+        .observe(latency.as_secs_f64());
+}
+
+/// Record a failure to connect to or to talk with an upstream
+pub fn record_upstream_error(upstream: &str, error: &str) {
+    UPSTREAM_ERRORS.with_label_values(&[upstream, error]).inc();
+}
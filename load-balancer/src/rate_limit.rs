@@ -0,0 +1,321 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use redis::{aio::ConnectionManager, Script};
+use tokio::sync::OnceCell;
+use tonic::{metadata::MetadataValue, Status};
+use tracing::warn;
+
+use crate::{LimitConfig, RateLimitConfig};
+
+/// the memory buckets are pruned of the idle ones beyond this count
+const MAX_BUCKETS: usize = 100_000;
+/// the memory buckets are pruned at most once in this interval
+const PRUNE_INTERVAL: Duration = Duration::from_secs(1);
+const RETRY_AFTER: &str = "retry-after";
+
+/// A token bucket in redis, the wait in milliseconds before a token is available
+/// is returned, 0 if a token is taken
+const TAKE_TOKEN: &str = r#"
+local rate = tonumber(ARGV[1])
+local burst = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(bucket[1]) or burst
+local ts = tonumber(bucket[2]) or now
+tokens = math.min(burst, tokens + (now - ts) * rate / 1000)
+local wait = 0
+if tokens >= 1 then
+  tokens = tokens - 1
+else
+  wait = math.ceil((1 - tokens) * 1000 / rate)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(burst * 1000 / rate))
+return wait
+"#;
+
+/// Limit the calls of each user to each method with token buckets
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    store: Store,
+}
+
+enum Store {
+    Memory(Mutex<MemoryStore>),
+    Redis(Box<RedisStore>),
+}
+
+#[derive(Default)]
+struct MemoryStore {
+    buckets: HashMap<String, Bucket>,
+    /// the buckets are not pruned before this time
+    next_prune: Option<Instant>,
+}
+
+struct RedisStore {
+    client: redis::Client,
+    /// connected on the first call, from the runtime of the proxy
+    conn: OnceCell<ConnectionManager>,
+    script: Script,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// limit of the method of the bucket
+    limit: LimitConfig,
+}
+
+impl RateLimiter {
+    pub fn try_new(config: RateLimitConfig) -> Result<Self> {
+        let limits = config.default.iter().map(|limit| ("default", limit));
+        for (name, limit) in limits.chain(config.methods.iter().map(|(k, v)| (k.as_str(), v))) {
+            validate(name, limit)?;
+        }
+
+        let store = match &config.redis {
+            Some(url) => Store::Redis(Box::new(RedisStore {
+                client: redis::Client::open(url.as_str())?,
+                conn: OnceCell::new(),
+                script: Script::new(TAKE_TOKEN),
+            })),
+            None => Store::Memory(Mutex::default()),
+        };
+        Ok(Self { config, store })
+    }
+
+    /// The limit of the method of the given path, the limit of its service is
+    /// used if the method has none
+    pub fn limit(&self, path: &str) -> Option<LimitConfig> {
+        let methods = &self.config.methods;
+        methods
+            .get(path)
+            .or_else(|| {
+                path.rsplit_once('/')
+                    .and_then(|(service, _)| methods.get(service))
+            })
+            .or(self.config.default.as_ref())
+            .copied()
+    }
+
+    /// Take a token of the bucket of the user and the method, an over-limit call
+    /// is rejected with the seconds to wait in the `retry-after` metadata
+    pub async fn check(&self, subject: &str, path: &str) -> Result<(), Status> {
+        let Some(limit) = self.limit(path) else {
+            return Ok(());
+        };
+        let key = format!("rate_limit:{subject}:{path}");
+        let wait = match self.take(&key, limit).await {
+            Ok(wait) => wait,
+            Err(e) => {
+                // an unavailable store must not take the services down
+                warn!("failed to check the rate limit of {key}: {e:#}");
+                None
+            }
+        };
+        let Some(wait) = wait else {
+            return Ok(());
+        };
+
+        let mut status = Status::resource_exhausted(format!("rate limit of {path} exceeded"));
+        let seconds = wait.as_secs_f64().ceil() as u64;
+        status
+            .metadata_mut()
+            .insert(RETRY_AFTER, MetadataValue::from(seconds.max(1)));
+        Err(status)
+    }
+
+    /// The wait before a token is available, none if a token is taken
+    async fn take(&self, key: &str, limit: LimitConfig) -> Result<Option<Duration>> {
+        match &self.store {
+            Store::Memory(store) => {
+                let mut store = store.lock().unwrap();
+                let now = Instant::now();
+                store.prune(now);
+                let bucket = store
+                    .buckets
+                    .entry(key.to_string())
+                    .or_insert_with(|| Bucket::new(limit, now));
+                Ok(bucket.take(now).err())
+            }
+            Store::Redis(redis) => {
+                let mut conn = redis
+                    .conn
+                    .get_or_try_init(|| redis.client.get_connection_manager())
+                    .await?
+                    .clone();
+                let wait: u64 = redis
+                    .script
+                    .key(key)
+                    .arg(limit.rate)
+                    .arg(limit.burst)
+                    .invoke_async(&mut conn)
+                    .await?;
+                Ok((wait > 0).then(|| Duration::from_millis(wait)))
+            }
+        }
+    }
+}
+
+impl MemoryStore {
+    /// Remove the buckets which have been idle long enough to be full again, they
+    /// are the same as new ones. Pruned once full, at most once in an interval
+    fn prune(&mut self, now: Instant) {
+        if self.buckets.len() < MAX_BUCKETS || self.next_prune.is_some_and(|t| now < t) {
+            return;
+        }
+        self.buckets.retain(|_, b| !b.is_idle(now));
+        self.next_prune = Some(now + PRUNE_INTERVAL);
+    }
+}
+
+impl Bucket {
+    fn new(limit: LimitConfig, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            updated: now,
+            limit,
+        }
+    }
+
+    /// Whether the bucket would be full if refilled now
+    fn is_idle(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * self.limit.rate >= self.limit.burst as f64
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst as f64);
+        self.updated = now;
+    }
+
+    /// Take a token, or the wait before one is available
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.limit.rate,
+            ))
+        }
+    }
+}
+
+/// A bucket of a zero or negative rate never refills, one of no burst never has a token
+fn validate(name: &str, limit: &LimitConfig) -> Result<()> {
+    if !limit.rate.is_finite() || limit.rate <= 0.0 {
+        bail!(
+            "rate limit of {name}: rate must be positive, got {}",
+            limit.rate
+        );
+    }
+    if limit.burst < 1 {
+        bail!("rate limit of {name}: burst must be at least 1");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        let config = serde_yaml::from_str(
+            r#"
+default: { rate: 10, burst: 20 }
+methods:
+  /notification.Notification/Send: { rate: 1, burst: 2 }
+  /user_stat.UserStats: { rate: 5, burst: 10 }
+"#,
+        )
+        .unwrap();
+        RateLimiter::try_new(config).unwrap()
+    }
+
+    #[test]
+    fn limit_should_fall_back_to_service_and_default() {
+        let limiter = limiter();
+        let limit = |path| limiter.limit(path).unwrap().burst;
+        assert_eq!(limit("/notification.Notification/Send"), 2);
+        assert_eq!(limit("/user_stat.UserStats/Query"), 10);
+        assert_eq!(limit("/metadata.Metadata/Materialize"), 20);
+    }
+
+    #[test]
+    fn invalid_limits_should_be_rejected() {
+        for limits in [
+            "default: { rate: 0, burst: 1 }",
+            "default: { rate: -1, burst: 1 }",
+            "methods: { /crm.Crm: { rate: 1, burst: 0 } }",
+        ] {
+            let config = serde_yaml::from_str(limits).unwrap();
+            assert!(RateLimiter::try_new(config).is_err(), "{limits}");
+        }
+    }
+
+    #[test]
+    fn bucket_should_refill_at_the_rate() {
+        let limit = LimitConfig {
+            rate: 2.0,
+            burst: 1,
+        };
+        let now = Instant::now();
+        let mut bucket = Bucket::new(limit, now);
+        assert!(bucket.take(now).is_ok());
+        let wait = bucket.take(now).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+        assert!(bucket.take(now + wait).is_ok());
+    }
+
+    #[test]
+    fn only_idle_buckets_should_be_pruned() {
+        let slow = LimitConfig {
+            rate: 1.0,
+            burst: 10,
+        };
+        let fast = LimitConfig {
+            rate: 100.0,
+            burst: 10,
+        };
+        let now = Instant::now();
+        let mut store = MemoryStore::default();
+        for i in 0..MAX_BUCKETS {
+            let limit = if i % 2 == 0 { slow } else { fast };
+            let mut bucket = Bucket::new(limit, now);
+            bucket.take(now).unwrap();
+            store.buckets.insert(i.to_string(), bucket);
+        }
+
+        // the fast buckets are full again after 10ms, the slow ones after 1s
+        let later = now + Duration::from_millis(100);
+        store.prune(later);
+        assert_eq!(store.buckets.len(), MAX_BUCKETS / 2);
+        let bucket = store.buckets["0"];
+        assert_eq!(bucket.tokens, 9.0);
+        assert_eq!(bucket.updated, now);
+    }
+
+    #[tokio::test]
+    async fn over_limit_call_should_be_exhausted_with_retry_after() {
+        let limiter = limiter();
+        let send = "/notification.Notification/Send";
+        assert!(limiter.check("alice@example.com", send).await.is_ok());
+        assert!(limiter.check("alice@example.com", send).await.is_ok());
+        // the buckets are per user
+        assert!(limiter.check("bob@example.com", send).await.is_ok());
+
+        let status = limiter.check("alice@example.com", send).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(status.metadata().get(RETRY_AFTER).unwrap(), "1");
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use pingora::{
+    http::RequestHeader,
+    protocols::ALPN,
+    services::{background::GenBackgroundService, Service},
+    upstreams::peer::HttpPeer,
+};
+use pingora_load_balancing::{
+    discovery::{ServiceDiscovery, Static},
+    selection::{BackendIter, BackendSelection, Consistent, RoundRobin},
+    Backend, Backends, LoadBalancer,
+};
+
+use crate::{
+    tls::PeerTls, FileDiscovery, GrpcHealthCheck, HashKey, SelectionConfig, UpstreamConfig, User,
+};
+
+/// the claims of the token a backend can be selected by
+const CLAIMS: [&str; 2] = ["email", "name"];
+
+/// A pool of backends of a service
+pub struct Upstream {
+    pub name: String,
+    pub prefix: String,
+    balancer: Balancer,
+    /// the backends are plaintext if not set
+    tls: Option<PeerTls>,
+}
+
+enum Balancer {
+    RoundRobin(Arc<LoadBalancer<RoundRobin>>),
+    Consistent {
+        lb: Arc<LoadBalancer<Consistent>>,
+        key: HashKey,
+        /// the turn of the requests without a key, which are balanced round robin
+        next: AtomicUsize,
+    },
+}
+
+/// Route the requests to the upstream of their gRPC path
+pub struct Router {
+    /// sorted by prefix length, the longest first
+    upstreams: Vec<Arc<Upstream>>,
+}
+
+impl Router {
+    pub fn try_new(configs: &[UpstreamConfig]) -> Result<Self> {
+        let mut upstreams = vec![];
+        for config in configs {
+            let balancer = match &config.selection {
+                SelectionConfig::RoundRobin => {
+                    Balancer::RoundRobin(Arc::new(load_balancer(config)?))
+                }
+                SelectionConfig::Consistent { key } => {
+                    if let HashKey::Claim(claim) = key {
+                        if !CLAIMS.contains(&claim.as_str()) {
+                            bail!("upstream {} is keyed by unknown claim {claim}", config.name);
+                        }
+                    }
+                    Balancer::Consistent {
+                        lb: Arc::new(load_balancer(config)?),
+                        key: key.clone(),
+                        next: AtomicUsize::new(0),
+                    }
+                }
+            };
+            let tls = config
+                .tls
+                .as_ref()
+                .map(PeerTls::load)
+                .transpose()
+                .with_context(|| format!("invalid TLS of upstream {}", config.name))?;
+            upstreams.push(Arc::new(Upstream {
+                name: config.name.clone(),
+                prefix: config.prefix.clone(),
+                balancer,
+                tls,
+            }));
+        }
+        upstreams.sort_by_key(|u| std::cmp::Reverse(u.prefix.len()));
+        Ok(Self { upstreams })
+    }
+
+    /// The upstream of the longest prefix of the path
+    pub fn route(&self, path: &str) -> Option<&Arc<Upstream>> {
+        self.upstreams.iter().find(|u| path.starts_with(&u.prefix))
+    }
+
+    /// The services discovering the backends and checking their health, the
+    /// backends are selected once these services have started
+    pub fn background_services(&self) -> Vec<Box<dyn Service>> {
+        self.upstreams
+            .iter()
+            .map(|u| {
+                let name = format!("upstream {}", u.name);
+                match &u.balancer {
+                    Balancer::RoundRobin(lb) => {
+                        Box::new(GenBackgroundService::new(name, lb.clone())) as Box<dyn Service>
+                    }
+                    Balancer::Consistent { lb, .. } => {
+                        Box::new(GenBackgroundService::new(name, lb.clone()))
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+impl Upstream {
+    /// The key selecting the backend of a request, none if the backends are not
+    /// selected by key or the request has no such key
+    pub fn hash_key(&self, req: &RequestHeader, user: &User) -> Option<Vec<u8>> {
+        let Balancer::Consistent { key, .. } = &self.balancer else {
+            return None;
+        };
+        let key = match key {
+            HashKey::Header(name) => req.headers.get(name.as_str())?.as_bytes(),
+            HashKey::Claim(claim) => match claim.as_str() {
+                "email" => user.email.as_bytes(),
+                "name" => user.name.as_bytes(),
+                _ => return None,
+            },
+        };
+        (!key.is_empty()).then(|| key.to_vec())
+    }
+
+    /// The peer of a backend speaking HTTP/2
+    pub fn peer(&self, backend: Backend) -> HttpPeer {
+        let mut peer = match &self.tls {
+            Some(tls) => tls.peer(backend),
+            None => HttpPeer::new(backend, false, String::default()),
+        };
+        peer.options.alpn = ALPN::H2;
+        peer
+    }
+
+    /// A healthy backend for the key, the backends are taken in turn without a key
+    pub fn select(&self, key: Option<&[u8]>) -> Option<Backend> {
+        // the backends of the key are tried in turn until a healthy one is found
+        const MAX_ITERATIONS: usize = 256;
+        match (&self.balancer, key) {
+            (Balancer::RoundRobin(lb), _) => lb.select(&[], MAX_ITERATIONS),
+            (Balancer::Consistent { lb, .. }, Some(key)) => lb.select(key, MAX_ITERATIONS),
+            // a single key would pin all the requests without one to the same backend
+            (Balancer::Consistent { lb, next, .. }, None) => {
+                let backends = lb.backends().get_backend();
+                let start = next.fetch_add(1, Ordering::Relaxed);
+                backends
+                    .iter()
+                    .cycle()
+                    .skip(start % backends.len().max(1))
+                    .take(backends.len())
+                    .find(|b| lb.backends().ready(b))
+                    .cloned()
+            }
+        }
+    }
+}
+
+fn load_balancer<S>(config: &UpstreamConfig) -> Result<LoadBalancer<S>>
+where
+    S: BackendSelection + 'static,
+    S::Iter: BackendIter,
+{
+    let discovery: Box<dyn ServiceDiscovery + Send + Sync> = match &config.discovery {
+        Some(discovery) => Box::new(FileDiscovery::new(&discovery.file)),
+        None if config.backends.is_empty() => bail!("upstream {} has no backend", config.name),
+        None => Static::try_from_iter(&config.backends)
+            .with_context(|| format!("invalid backends of upstream {}", config.name))?,
+    };
+
+    let mut backends = Backends::new(discovery);
+    if let Some(health_check) = &config.health_check {
+        let tls = config
+            .tls
+            .as_ref()
+            .map(|tls| tls.client_config())
+            .transpose()?;
+        backends.set_health_check(Box::new(GrpcHealthCheck::new(health_check, tls)));
+    }
+
+    let mut lb = LoadBalancer::from_backends(backends);
+    lb.update_frequency = config
+        .discovery
+        .as_ref()
+        .map(|d| Duration::from_secs(d.interval));
+    lb.health_check_frequency = config
+        .health_check
+        .as_ref()
+        .map(|h| Duration::from_secs(h.interval));
+    lb.parallel_health_check = true;
+    Ok(lb)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn upstream(name: &str, prefix: &str, port: u16) -> UpstreamConfig {
+        UpstreamConfig {
+            name: name.to_string(),
+            prefix: prefix.to_string(),
+            backends: vec![format!("127.0.0.1:{port}")],
+            discovery: None,
+            health_check: None,
+            selection: SelectionConfig::RoundRobin,
+            tls: None,
+        }
+    }
+
+    #[test]
+    fn requests_should_be_routed_by_path_prefix() {
+        let router = Router::try_new(&[
+            upstream("crm", "/crm.", 50000),
+            upstream("users", "/crm.UserService/", 50004),
+            upstream("notification", "/notification.Notification/", 50003),
+        ])
+        .unwrap();
+
+        let route = |path| router.route(path).map(|u| u.name.as_str());
+        assert_eq!(
+            route("/notification.Notification/Send"),
+            Some("notification")
+        );
+        assert_eq!(route("/crm.UserService/GetUser"), Some("users"));
+        assert_eq!(route("/crm.Crm/Welcome"), Some("crm"));
+        assert_eq!(route("/metadata.Metadata/Materialize"), None);
+    }
+
+    #[test]
+    fn requests_of_a_key_should_select_the_same_backend() {
+        let mut config = upstream("notification", "/notification.Notification/", 50003);
+        config.backends = (50010..50020).map(|p| format!("127.0.0.1:{p}")).collect();
+        config.selection = SelectionConfig::Consistent {
+            key: HashKey::Claim("email".to_string()),
+        };
+        let router = Router::try_new(&[config]).unwrap();
+        let upstream = router.route("/notification.Notification/Send").unwrap();
+        let Balancer::Consistent { lb, .. } = &upstream.balancer else {
+            panic!("expected consistent hashing");
+        };
+        futures::executor::block_on(lb.update()).unwrap();
+
+        let user = User {
+            name: "Alice".to_string(),
+            email: "alice@example.com".to_string(),
+            roles: vec![],
+            scopes: vec![],
+        };
+        let req = RequestHeader::build("POST", b"/notification.Notification/Send", None).unwrap();
+        let key = upstream.hash_key(&req, &user).unwrap();
+        assert_eq!(key, b"alice@example.com");
+        let backend = upstream.select(Some(&key)).unwrap();
+        for _ in 0..10 {
+            assert_eq!(upstream.select(Some(&key)).unwrap(), backend);
+        }
+    }
+
+    #[test]
+    fn requests_without_a_key_should_select_the_backends_in_turn() {
+        let mut config = upstream("notification", "/notification.Notification/", 50003);
+        config.backends = (50010..50020).map(|p| format!("127.0.0.1:{p}")).collect();
+        config.selection = SelectionConfig::Consistent {
+            key: HashKey::Header("x-tenant".to_string()),
+        };
+        let router = Router::try_new(&[config]).unwrap();
+        let upstream = router.route("/notification.Notification/Send").unwrap();
+        let Balancer::Consistent { lb, .. } = &upstream.balancer else {
+            panic!("expected consistent hashing");
+        };
+        futures::executor::block_on(lb.update()).unwrap();
+
+        let user = User {
+            name: "Alice".to_string(),
+            email: "alice@example.com".to_string(),
+            roles: vec![],
+            scopes: vec![],
+        };
+        let req = RequestHeader::build("POST", b"/notification.Notification/Send", None).unwrap();
+        let key = upstream.hash_key(&req, &user);
+        assert_eq!(key, None);
+        let backends: HashSet<_> = (0..10).map(|_| upstream.select(None).unwrap()).collect();
+        assert_eq!(backends.len(), 10);
+    }
+
+    #[test]
+    fn unknown_claim_should_be_rejected() {
+        let mut config = upstream("crm", "/crm.", 50000);
+        config.selection = SelectionConfig::Consistent {
+            key: HashKey::Claim("jti".to_string()),
+        };
+        assert!(Router::try_new(&[config]).is_err());
+    }
+}
@@ -0,0 +1,64 @@
+use crm_core::auth::status_response;
+use pingora::{
+    http::ResponseHeader,
+    proxy::Session,
+    Error, ErrorSource,
+    ErrorType::{ConnectionClosed, HTTPStatus, ReadError, WriteError},
+    Result,
+};
+use tonic::{Code, Status};
+
+/// Respond with a trailers-only gRPC response of the status, the HTTP status is
+/// always 200 and the error is carried by `grpc-status` and `grpc-message`
+pub async fn respond_status(session: &mut Session, status: Status) -> Result<()> {
+    let res = status_response::<()>(status);
+    let mut header = ResponseHeader::build(200, Some(res.headers().len()))?;
+    for (name, value) in res.headers() {
+        header.insert_header(name.clone(), value.clone())?;
+    }
+    session.write_response_header(Box::new(header), true).await
+}
+
+/// The status sent to the client on a failure of the proxy, none if the client
+/// connection is already gone
+pub fn error_status(e: &Error) -> Option<Status> {
+    let message = e.to_string();
+    let code = match (e.esource(), e.etype()) {
+        (_, HTTPStatus(status)) => http_code(*status),
+        (ErrorSource::Downstream, WriteError | ReadError | ConnectionClosed) => return None,
+        (ErrorSource::Downstream, _) => Code::Internal,
+        (ErrorSource::Upstream, _) => Code::Unavailable,
+        (ErrorSource::Internal | ErrorSource::Unset, _) => Code::Internal,
+    };
+    Some(Status::new(code, message))
+}
+
+/// The gRPC code of an HTTP status, as specified by the gRPC HTTP/2 protocol
+fn http_code(status: u16) -> Code {
+    match status {
+        400 => Code::Internal,
+        401 => Code::Unauthenticated,
+        403 => Code::PermissionDenied,
+        404 => Code::Unimplemented,
+        429 | 502 | 503 | 504 => Code::Unavailable,
+        _ => Code::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pingora::ErrorType;
+
+    #[test]
+    fn proxy_errors_should_map_to_grpc_codes() {
+        let e = Error::explain(HTTPStatus(503), "no backend");
+        assert_eq!(error_status(&e).unwrap().code(), Code::Unavailable);
+
+        let e = Error::new_up(ErrorType::ConnectRefused);
+        assert_eq!(error_status(&e).unwrap().code(), Code::Unavailable);
+
+        let e = Error::new_down(WriteError);
+        assert!(error_status(&e).is_none());
+    }
+}
@@ -0,0 +1,57 @@
+use std::{fs, sync::Arc};
+
+use anyhow::{Context, Result};
+use crm_core::TlsConfig;
+use pingora::{
+    tls::{pkey::PKey, x509::X509},
+    upstreams::peer::HttpPeer,
+    utils::CertKey,
+};
+use pingora_load_balancing::Backend;
+
+/// The TLS of the connections to the instances of an upstream, the proxy
+/// presents its client certificate to them
+pub struct PeerTls {
+    cert_key: Arc<CertKey>,
+    ca: Option<Arc<Box<[X509]>>>,
+    /// the server name checked against the certificates of the instances
+    domain: Option<String>,
+}
+
+impl PeerTls {
+    pub fn load(config: &TlsConfig) -> Result<Self> {
+        let certs = X509::stack_from_pem(&read(&config.cert)?)
+            .with_context(|| format!("invalid certificate {}", config.cert.display()))?;
+        let key = PKey::private_key_from_pem(&read(&config.key)?)
+            .with_context(|| format!("invalid key {}", config.key.display()))?;
+        let ca = config
+            .ca
+            .as_ref()
+            .map(|ca| {
+                let certs = X509::stack_from_pem(&read(ca)?)
+                    .with_context(|| format!("invalid CA certificate {}", ca.display()))?;
+                Ok::<_, anyhow::Error>(Arc::new(certs.into_boxed_slice()))
+            })
+            .transpose()?;
+        Ok(Self {
+            cert_key: Arc::new(CertKey::new(certs, key)),
+            ca,
+            domain: config.domain.clone(),
+        })
+    }
+
+    /// A peer of the backend connected over TLS
+    pub fn peer(&self, backend: Backend) -> HttpPeer {
+        let sni = self.domain.clone().unwrap_or_default();
+        let mut peer = HttpPeer::new(backend, true, sni);
+        peer.client_cert_key = Some(self.cert_key.clone());
+        peer.options.ca.clone_from(&self.ca);
+        // the backends are addressed by IP, their name is only known if configured
+        peer.options.verify_hostname = self.domain.is_some();
+        peer
+    }
+}
+
+fn read(path: &std::path::Path) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("failed to read {}", path.display()))
+}
@@ -8,6 +8,7 @@ fn main() -> Result<()> {
     let builder = tonic_build::configure();
     builder
         .out_dir("src/pb")
+        .file_descriptor_set_path("src/pb/user_stat_descriptor.bin")
         .type_attribute(
             "user_stat.User",
             "#[derive(serde::Serialize, serde::Deserialize)]",
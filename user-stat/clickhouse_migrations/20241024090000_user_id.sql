@@ -0,0 +1,6 @@
+-- Add the id column of assets/clickhouse to a table created before it, run with
+-- clickhouse-client --multiquery < clickhouse_migrations/20241024090000_user_id.sql
+ALTER TABLE crm.user_stat ADD COLUMN IF NOT EXISTS id UInt64 DEFAULT cityHash64(email) FIRST;
+
+-- write the ids of the existing rows, which are computed on read until then
+ALTER TABLE crm.user_stat MATERIALIZE COLUMN id;
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE user_stats ADD COLUMN id bigserial UNIQUE;
@@ -2,20 +2,46 @@ use anyhow::Result;
 use async_stream::stream;
 use chrono::{DateTime, TimeZone, Utc};
 use clickhouse::{query::Query, sql::Identifier};
+use crm_core::health::Ping;
 use futures::Stream;
 use prost_types::Timestamp;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 use tonic::{Code, Status};
 use tracing::{debug, error, instrument};
 
-use crate::pb::{QueryRequest, User};
+use crate::{
+    metrics::record_users_queried,
+    pb::{QueryRequest, User},
+};
 
-use super::{Repo, UserRow};
+use super::{Repo, UserRecord, UserRow, UserStore};
+
+const REPO: &str = "clickhouse";
 
 #[derive(Clone)]
 pub struct ClickHouseRepo {
     client: clickhouse::Client,
 }
 
+#[derive(clickhouse::Row, Serialize, Deserialize)]
+struct UserRecordRow {
+    id: u64,
+    name: String,
+    email: String,
+    #[serde(with = "clickhouse::serde::time::datetime")]
+    created_at: OffsetDateTime,
+}
+
+/// A user to be inserted, the id defaults to the hash of the email
+#[derive(clickhouse::Row, Serialize)]
+struct NewUserRow<'a> {
+    email: &'a str,
+    name: &'a str,
+    #[serde(with = "clickhouse::serde::time::datetime")]
+    created_at: OffsetDateTime,
+}
+
 impl ClickHouseRepo {
     pub fn new(client: clickhouse::Client) -> Self {
         Self { client }
@@ -92,6 +118,13 @@ impl ClickHouseRepo {
     }
 }
 
+impl Ping for ClickHouseRepo {
+    async fn ping(&self) -> Result<()> {
+        self.client.query("SELECT 1").execute().await?;
+        Ok(())
+    }
+}
+
 impl Repo for ClickHouseRepo {
     #[instrument(name = "query-clickhouse", skip_all)]
     async fn query(
@@ -108,6 +141,7 @@ impl Repo for ClickHouseRepo {
 
         Ok(stream! {
             while let Some(row) = cursor.next().await.map_err(|e| Status::internal(e.to_string()))? {
+                record_users_queried(REPO, 1);
                 yield Ok(row.into());
             }
         })
@@ -126,12 +160,92 @@ impl Repo for ClickHouseRepo {
 
         Ok(stream! {
             while let Some(row) = cursor.next().await.map_err(|e| Status::internal(e.to_string()))? {
+                record_users_queried(REPO, 1);
                 yield Ok(row.into());
             }
         })
     }
 }
 
+impl UserStore for ClickHouseRepo {
+    #[instrument(name = "get-user-clickhouse", skip(self))]
+    async fn get_user(&self, id: u64) -> Result<Option<UserRecord>> {
+        let row = self
+            .client
+            .query("SELECT ?fields FROM ? WHERE id = ? LIMIT 1")
+            .bind(Identifier("user_stat"))
+            .bind(id)
+            .fetch_optional::<UserRecordRow>()
+            .await?;
+
+        Ok(row.map(|row| row.into()))
+    }
+
+    /// The email is checked before inserting, as there are no unique constraints
+    /// in ClickHouse, users created concurrently with the same email may both succeed
+    #[instrument(name = "create-user-clickhouse", skip_all)]
+    async fn create_user(&self, name: &str, email: &str) -> Result<Option<UserRecord>> {
+        let exists = self
+            .client
+            .query("SELECT count() FROM ? WHERE email = ?")
+            .bind(Identifier("user_stat"))
+            .bind(email)
+            .fetch_one::<u64>()
+            .await?
+            > 0;
+        if exists {
+            return Ok(None);
+        }
+
+        let mut insert = self.client.insert("user_stat")?;
+        insert
+            .write(&NewUserRow {
+                email,
+                name,
+                created_at: OffsetDateTime::now_utc(),
+            })
+            .await?;
+        insert.end().await?;
+
+        let row = self
+            .client
+            .query("SELECT ?fields FROM ? WHERE email = ? LIMIT 1")
+            .bind(Identifier("user_stat"))
+            .bind(email)
+            .fetch_one::<UserRecordRow>()
+            .await?;
+
+        Ok(Some(row.into()))
+    }
+
+    #[instrument(name = "list-users-clickhouse", skip(self))]
+    async fn list_users(&self, from_id: u64, limit: u32) -> Result<Vec<UserRecord>> {
+        let rows = self
+            .client
+            .query("SELECT ?fields FROM ? WHERE id >= ? ORDER BY id LIMIT ?")
+            .bind(Identifier("user_stat"))
+            .bind(from_id)
+            .bind(limit)
+            .fetch_all::<UserRecordRow>()
+            .await?;
+
+        Ok(rows.into_iter().map(|row| row.into()).collect())
+    }
+}
+
+impl From<UserRecordRow> for UserRecord {
+    fn from(row: UserRecordRow) -> Self {
+        UserRecord {
+            id: row.id,
+            name: row.name,
+            email: row.email,
+            created_at: Utc
+                .timestamp_opt(row.created_at.unix_timestamp(), 0)
+                .single(),
+        }
+    }
+}
+
 fn ids_query<'a>(name: &str, ids: &'a [u32]) -> (String, Option<&'a [u32]>) {
     if ids.is_empty() {
         return ("TRUE".to_string(), None);
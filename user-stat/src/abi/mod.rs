@@ -50,6 +50,37 @@ impl QueryRequest {
     }
 }
 
+/// A user of the store, the id is generated by the store
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserRecord {
+    pub id: u64,
+    pub name: String,
+    pub email: String,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A store of the users which are queried by [`Repo`], users are unique by email
+pub trait UserStore: Send + Sync + 'static {
+    fn get_user(
+        &self,
+        id: u64,
+    ) -> impl std::future::Future<Output = Result<Option<UserRecord>>> + Send;
+
+    /// Create a user registered now, none if the email is taken
+    fn create_user(
+        &self,
+        name: &str,
+        email: &str,
+    ) -> impl std::future::Future<Output = Result<Option<UserRecord>>> + Send;
+
+    /// At most `limit` users whose id is not less than `from_id`, ordered by id
+    fn list_users(
+        &self,
+        from_id: u64,
+        limit: u32,
+    ) -> impl std::future::Future<Output = Result<Vec<UserRecord>>> + Send;
+}
+
 /// A repository that can be used to query user stats
 pub trait Repo: Send + Sync + 'static {
     fn query(
@@ -1,17 +1,31 @@
 use anyhow::Result;
 use chrono::{DateTime, TimeZone as _, Utc};
+use crm_core::health::Ping;
 use futures::Stream;
 use prost_types::Timestamp;
 use sqlx::{PgPool, Postgres, QueryBuilder};
 use tonic::Status;
 use tracing::instrument;
 
-use super::{QueryRequest, Repo, User, UserRow};
+use crate::metrics::record_users_queried;
 
+use super::{QueryRequest, Repo, User, UserRecord, UserRow, UserStore};
+
+const REPO: &str = "postgres";
+
+#[derive(Clone)]
 pub struct PostgresRepo {
     pool: PgPool,
 }
 
+#[derive(sqlx::FromRow)]
+struct UserRecordRow {
+    id: i64,
+    name: String,
+    email: String,
+    created_at: Option<DateTime<Utc>>,
+}
+
 impl PostgresRepo {
     pub async fn new(url: &str) -> Result<Self> {
         let pool = PgPool::connect(url).await?;
@@ -64,6 +78,13 @@ impl PostgresRepo {
     }
 }
 
+impl Ping for PostgresRepo {
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
 impl Repo for PostgresRepo {
     #[instrument(name = "query-postgres", skip_all)]
     async fn query(
@@ -76,6 +97,7 @@ impl Repo for PostgresRepo {
             .build_query_as::<UserRow>()
             .fetch_all(&self.pool)
             .await?;
+        record_users_queried(REPO, ret.len());
 
         Ok(futures::stream::iter(
             ret.into_iter().map(|row| Ok(row.into())),
@@ -90,6 +112,7 @@ impl Repo for PostgresRepo {
         let ret = sqlx::query_as::<_, UserRow>(&query)
             .fetch_all(&self.pool)
             .await?;
+        record_users_queried(REPO, ret.len());
 
         Ok(futures::stream::iter(
             ret.into_iter().map(|row| Ok(row.into())),
@@ -97,6 +120,60 @@ impl Repo for PostgresRepo {
     }
 }
 
+impl UserStore for PostgresRepo {
+    #[instrument(name = "get-user-postgres", skip_all)]
+    async fn get_user(&self, id: u64) -> Result<Option<UserRecord>> {
+        let row = sqlx::query_as::<_, UserRecordRow>(
+            "SELECT id, name, email, created_at FROM user_stats WHERE id = $1",
+        )
+        .bind(id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| row.into()))
+    }
+
+    #[instrument(name = "create-user-postgres", skip_all)]
+    async fn create_user(&self, name: &str, email: &str) -> Result<Option<UserRecord>> {
+        let row = sqlx::query_as::<_, UserRecordRow>(
+            "INSERT INTO user_stats(email, name) VALUES ($1, $2)
+            ON CONFLICT (email) DO NOTHING
+            RETURNING id, name, email, created_at",
+        )
+        .bind(email)
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| row.into()))
+    }
+
+    #[instrument(name = "list-users-postgres", skip_all)]
+    async fn list_users(&self, from_id: u64, limit: u32) -> Result<Vec<UserRecord>> {
+        let rows = sqlx::query_as::<_, UserRecordRow>(
+            "SELECT id, name, email, created_at FROM user_stats
+            WHERE id >= $1 ORDER BY id LIMIT $2",
+        )
+        .bind(from_id as i64)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| row.into()).collect())
+    }
+}
+
+impl From<UserRecordRow> for UserRecord {
+    fn from(row: UserRecordRow) -> Self {
+        UserRecord {
+            id: row.id as _,
+            name: row.name,
+            email: row.email,
+            created_at: row.created_at,
+        }
+    }
+}
+
 fn ts_to_utc(ts: &Timestamp) -> DateTime<Utc> {
     Utc.timestamp_opt(ts.seconds, ts.nanos as _).unwrap()
 }
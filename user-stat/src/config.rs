@@ -1,4 +1,4 @@
-use crm_core::telemetry;
+use crm_core::{auth::AuthConfig, telemetry, TlsConfig};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -8,17 +8,14 @@ pub struct AppConfig {
     pub telemetry: telemetry::Config,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AuthConfig {
-    pub pk: String,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub port: u16,
     pub db_url: String,
     pub db_name: String,
     pub db_type: DBType,
+    /// serve over TLS if set
+    pub tls: Option<TlsConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -2,12 +2,13 @@
 
 mod abi;
 mod config;
+mod metrics;
 pub mod pb;
 
 use std::pin::Pin;
 use std::{ops::Deref, sync::Arc};
 
-pub use abi::{ClickHouseRepo, PostgresRepo, Repo, UserRow};
+pub use abi::{ClickHouseRepo, PostgresRepo, Repo, UserRecord, UserRow, UserStore};
 pub use config::{AppConfig, DBType};
 use futures::Stream;
 use pb::{
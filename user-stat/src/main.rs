@@ -1,11 +1,17 @@
 use anyhow::Result;
 use clickhouse::Client;
-use crm_core::{accept_trace, log_error, make_span, telemetry, ConfigExt};
+use crm_core::{
+    accept_trace, auth::AuthLayer, health::report_ping, log_error, make_span,
+    metrics::GrpcMetricsLayer, telemetry, ConfigExt,
+};
 use tonic::transport::Server;
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
 use tracing::info;
-use user_stat::{AppConfig, ClickHouseRepo, DBType, PostgresRepo, UserStatsService};
+use user_stat::{
+    pb::{user_stats_server::SERVICE_NAME, FILE_DESCRIPTOR_SET},
+    AppConfig, ClickHouseRepo, DBType, PostgresRepo, UserStatsService,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -17,8 +23,22 @@ async fn main() -> Result<()> {
     let addr = format!("127.0.0.1:{}", addr).parse().unwrap();
     info!("User-Stat service listening on {}", addr);
 
-    let mut server = Server::builder().layer(
+    let dk = config.auth.decoding_key()?;
+    let authz = config.auth.authz_layer();
+
+    let server = match &config.server.tls {
+        Some(tls) => Server::builder().tls_config(tls.server_config()?)?,
+        None => {
+            info!("TLS is not enabled");
+            Server::builder()
+        }
+    };
+    let mut server = server.layer(
         ServiceBuilder::new()
+            .layer(GrpcMetricsLayer::new(&[
+                FILE_DESCRIPTOR_SET,
+                tonic_reflection::pb::v1::FILE_DESCRIPTOR_SET,
+            ])?)
             .layer(tower::timeout::TimeoutLayer::new(
                 std::time::Duration::from_secs(30),
             ))
@@ -26,8 +46,15 @@ async fn main() -> Result<()> {
                 TraceLayer::new_for_grpc()
                     .make_span_with(make_span)
                     .on_request(accept_trace),
-            ),
+            )
+            .layer(AuthLayer::new(dk))
+            .layer(authz),
     );
+    let (reporter, health) = tonic_health::server::health_reporter();
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .build_v1()?;
+    let server = server.add_service(health).add_service(reflection);
 
     // initialize service with different db type by db_type in configuration file
     // and add it into server
@@ -39,6 +66,7 @@ async fn main() -> Result<()> {
                     .with_url(&config.server.db_url)
                     .with_database(&config.server.db_name),
             );
+            report_ping(reporter, vec![SERVICE_NAME, ""], repo.clone());
             let svc = UserStatsService::new(repo, config).await.into_server();
             server.add_service(svc)
         }
@@ -49,6 +77,7 @@ async fn main() -> Result<()> {
                 config.server.db_url, config.server.db_name
             ))
             .await?;
+            report_ping(reporter, vec![SERVICE_NAME, ""], repo.clone());
             let svc = UserStatsService::new(repo, config).await.into_server();
             server.add_service(svc)
         }
@@ -0,0 +1,19 @@
+use std::sync::LazyLock;
+
+use prometheus::{register_int_counter_vec, IntCounterVec};
+
+static USERS_QUERIED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "user_stat_users_queried_total",
+        "Users returned by the queries by repo",
+        &["repo"]
+    )
+    .expect("metric is registered once")
+});
+
+/// Record users returned by a query of the repo
+pub(crate) fn record_users_queried(repo: &str, count: usize) {
+    USERS_QUERIED
+        .with_label_values(&[repo])
+        .inc_by(count as u64);
+}
@@ -1,3 +1,6 @@
 mod user_stat;
 
 pub use user_stat::*;
+
+/// Encoded descriptors of the protos, served by the reflection service
+pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!("user_stat_descriptor.bin");